@@ -0,0 +1,283 @@
+use crate::*;
+
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnaryOp {
+	Not,
+	Neg,
+	Len,
+	Floor,
+	Ceil,
+	Round,
+	Sqrt,
+	Abs,
+	Trunc,
+	Sign,
+	Sin,
+	Cos,
+	Tan,
+	Asin,
+	Acos,
+	Atan,
+	Ln,
+	Log10,
+	Log2,
+	Exp,
+	CharToNum,
+	NumToDigit,
+	NumToString,
+	NumToChar,
+	ToInt,
+	ToFloat,
+	Kind,
+	Message,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinaryOp {
+	Add,
+	Sub,
+	Mul,
+	Div,
+	Mod,
+	// Exponentiation on numbers, xor on booleans
+	Pow,
+	And,
+	Or,
+	Eq,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+	Join,
+	Index,
+}
+
+// Functions that are called like script functions but run natively
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Builtin {
+	Map,
+	Filter,
+	Fold,
+	Reduce,
+	Sort,
+	SortBy,
+	Reverse,
+	Find,
+	Any,
+	All,
+	Zip,
+	Enumerate,
+	Min,
+	Max,
+	Clamp,
+	Atan2,
+	Hypot,
+	Log,
+}
+
+impl fmt::Display for UnaryOp {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let s = match self {
+			UnaryOp::Not => "!",
+			UnaryOp::Neg => "-",
+			UnaryOp::Len => "len",
+			UnaryOp::Floor => "floor",
+			UnaryOp::Ceil => "ceil",
+			UnaryOp::Round => "round",
+			UnaryOp::Sqrt => "sqrt",
+			UnaryOp::Abs => "abs",
+			UnaryOp::Trunc => "trunc",
+			UnaryOp::Sign => "sign",
+			UnaryOp::Sin => "sin",
+			UnaryOp::Cos => "cos",
+			UnaryOp::Tan => "tan",
+			UnaryOp::Asin => "asin",
+			UnaryOp::Acos => "acos",
+			UnaryOp::Atan => "atan",
+			UnaryOp::Ln => "ln",
+			UnaryOp::Log10 => "log10",
+			UnaryOp::Log2 => "log2",
+			UnaryOp::Exp => "exp",
+			UnaryOp::CharToNum => "n",
+			UnaryOp::NumToDigit => "dig",
+			UnaryOp::NumToString => "num",
+			UnaryOp::NumToChar => "c",
+			UnaryOp::ToInt => "int",
+			UnaryOp::ToFloat => "float",
+			UnaryOp::Kind => "kind",
+			UnaryOp::Message => "message",
+		};
+		write!(f, "{}", s)
+	}
+}
+
+impl fmt::Display for BinaryOp {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let s = match self {
+			BinaryOp::Add => "+",
+			BinaryOp::Sub => "-",
+			BinaryOp::Mul => "*",
+			BinaryOp::Div => "/",
+			BinaryOp::Mod => "%",
+			BinaryOp::Pow => "^",
+			BinaryOp::And => "&",
+			BinaryOp::Or => "|",
+			BinaryOp::Eq => "==",
+			BinaryOp::Lt => "<",
+			BinaryOp::Le => "<=",
+			BinaryOp::Gt => ">",
+			BinaryOp::Ge => ">=",
+			BinaryOp::Join => "++",
+			BinaryOp::Index => "@",
+		};
+		write!(f, "{}", s)
+	}
+}
+
+// (name, type, default value) for each parameter of a function
+pub type Params = Vec<(String, VariableT, Option<Expr>)>;
+
+impl fmt::Display for Builtin {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let s = match self {
+			Builtin::Map => "map",
+			Builtin::Filter => "filter",
+			Builtin::Fold => "fold",
+			Builtin::Reduce => "reduce",
+			Builtin::Sort => "sort",
+			Builtin::SortBy => "sort_by",
+			Builtin::Reverse => "reverse",
+			Builtin::Find => "find",
+			Builtin::Any => "any",
+			Builtin::All => "all",
+			Builtin::Zip => "zip",
+			Builtin::Enumerate => "enumerate",
+			Builtin::Min => "min",
+			Builtin::Max => "max",
+			Builtin::Clamp => "clamp",
+			Builtin::Atan2 => "atan2",
+			Builtin::Hypot => "hypot",
+			Builtin::Log => "log",
+		};
+		write!(f, "{}", s)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+	pub kind: ExprKind,
+	pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+	Literal(Variable),
+	Name(String),
+	List(Vec<Expr>),
+	Unary(UnaryOp, Box<Expr>),
+	Binary(BinaryOp, Box<Expr>, Box<Expr>),
+	// list + index item
+	Insert(Box<Expr>, Box<Expr>, Box<Expr>),
+	// Function and arguments
+	Call(Box<Expr>, Vec<Expr>),
+	// Built-in, its arguments and an operator given as the callback instead of a function
+	Builtin(Builtin, Vec<Expr>, Option<BinaryOp>),
+	// start..end or start..=end, with the step. The end can only be left out when slicing
+	Range(Box<Expr>, Option<Box<Expr>>, bool, Option<Box<Expr>>),
+	// list @ range
+	Slice(Box<Expr>, Box<Expr>),
+	// Parameters with their default values, return type and the index of the statement, as the
+	// body is on the lines after it
+	Function(Params, Option<VariableT>, usize),
+}
+
+impl Expr {
+	fn has_function(&self) -> bool {
+		match &self.kind {
+			ExprKind::Literal(_) | ExprKind::Name(_) => false,
+			ExprKind::List(items) | ExprKind::Builtin(_, items, _) => {
+				items.iter().any(Expr::has_function)
+			}
+			ExprKind::Call(function, args) => {
+				function.has_function() || args.iter().any(Expr::has_function)
+			}
+			ExprKind::Unary(_, operand) => operand.has_function(),
+			ExprKind::Binary(_, lhs, rhs) => lhs.has_function() || rhs.has_function(),
+			ExprKind::Insert(list, index, item) => {
+				list.has_function() || index.has_function() || item.has_function()
+			}
+			ExprKind::Range(start, end, _, step) => {
+				start.has_function() || end.iter().chain(step.iter()).any(|e| e.has_function())
+			}
+			ExprKind::Slice(list, range) => list.has_function() || range.has_function(),
+			ExprKind::Function(..) => true,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+	Exit,
+	// Declares a variable in the current block. With indices it changes an item of a list instead,
+	// copying the list into the current block if it belongs to an enclosing one
+	Let(String, Vec<Expr>, Option<VariableT>, Option<Expr>),
+	// Assigns to an existing variable, or an item of it, which may belong to an enclosing block
+	Set(String, Vec<Expr>, Option<VariableT>, Option<Expr>),
+	// Lets the current function assign to these global variables
+	Global(Vec<String>),
+	If(Expr),
+	Elif(Expr),
+	Else,
+	EndIf,
+	Print(Vec<Expr>),
+	Clear,
+	Label(String),
+	Jump(String),
+	JumpRel(Expr),
+	Type(Expr),
+	End,
+	Return(Option<Expr>),
+	// Name, parameters with their default values and return type
+	Function(String, Params, Option<VariableT>),
+	Puts(Expr),
+	Try,
+	Catch(String),
+	EndTry,
+	While(Expr),
+	EndWhile,
+	// for name in start..end
+	ForRange(String, Expr, Expr),
+	// for name in list
+	ForEach(String, Expr),
+	EndFor,
+	Break,
+	Continue,
+	Expr(Expr),
+}
+
+impl Statement {
+	/// Whether the lines after this statement are the body of a function, up to the matching `end`
+	pub fn opens_function(&self) -> bool {
+		let exprs: Vec<&Expr> = match self {
+			Statement::Function(..) => return true,
+			Statement::Let(_, indices, _, value) | Statement::Set(_, indices, _, value) => {
+				indices.iter().chain(value.iter()).collect()
+			}
+			Statement::Return(value) => value.iter().collect(),
+			Statement::If(expr)
+			| Statement::Elif(expr)
+			| Statement::JumpRel(expr)
+			| Statement::Type(expr)
+			| Statement::Puts(expr)
+			| Statement::While(expr)
+			| Statement::ForEach(_, expr)
+			| Statement::Expr(expr) => vec![expr],
+			Statement::ForRange(_, start, end) => vec![start, end],
+			Statement::Print(exprs) => exprs.iter().collect(),
+			_ => Vec::new(),
+		};
+		exprs.into_iter().any(Expr::has_function)
+	}
+}
@@ -1,67 +1,51 @@
 use crate::*;
 
-fn evaluate_char(chr: &str) -> Result<Variable, CustomErr> {
-	let mut chars = chr.chars();
-	let first = chars.next();
-	let second = chars.next();
-	let third = chars.next();
-	let fourth = chars.next();
-	match (first, second, third, fourth) {
-		(Some('\''), Some(c), Some('\''), None) => Ok(Char(c)),
-		_ => perr!(),
-	}
-}
-
-fn parse_or_get(s: &str, variables: &Variables) -> Result<Variable, CustomErr> {
-	let val = if helper::has_parentheses(s) {
-		variable::evaluate_statement(&helper::split(helper::remove_parentheses(s))?, variables)?
-	} else if let Some(n) = variables.get(s) {
-		n.clone()
-	} else if let Ok(n) = evaluate_char(s) {
-		n
-	} else {
-		return perr!();
-	};
-	variable::assert_type_of(&val, &CharT)?;
-	Ok(val)
-}
+use std::convert::TryFrom;
 
-pub fn char_op(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	match words {
-		["n", statement] => {
-			let c = variable::un_char(&parse_or_get(statement, variables)?)?;
-			Ok(Number(c as u8 as f64))
+pub fn convert_op(op: UnaryOp, operand: Variable) -> Result<Variable, CustomErr> {
+	match op {
+		UnaryOp::CharToNum => {
+			let c = variable::un_char(&operand)?;
+			Ok(Int(c as i64))
 		}
-		["dig", statement] => {
-			let c = variable::un_number(&floats::parse_or_get(statement, variables)?)?;
-			if c < 0. || 9. < c {
-				serr!()
+		UnaryOp::NumToDigit => {
+			let c = variable::un_int(&operand)?;
+			if !(0..=9).contains(&c) {
+				serr!("`dig` expects a number from 0 to 9, found {}", c)
 			} else {
 				Ok(Char((c as u8 + b'0') as char))
 			}
 		}
-		["num", statement] => {
-			let c = variable::un_number(&floats::parse_or_get(statement, variables)?)?;
-			Ok(List(CharT, format!("{}", c).chars().map(Char).collect()))
+		UnaryOp::NumToString => match operand {
+			Number(_) | Int(_) => Ok(variable::string(&operand.to_string())),
+			_ => terr!("expected Number, found {}", variable::to_type(&operand)),
+		},
+		UnaryOp::NumToChar => {
+			let n = variable::un_int(&operand)?;
+			u32::try_from(n)
+				.ok()
+				.and_then(char::from_u32)
+				.map(Char)
+				.ok_or_else(|| serrE!("`c` expects a character code, found {}", n).into())
 		}
-		["c", statement] => {
-			let n = variable::un_number(&floats::parse_or_get(statement, variables)?)?;
-			Ok(Char(n as u8 as char))
-		}
-		[lhs, op, rhs] => {
-			let f = match *op {
-				"==" => |l, r| l == r,
-				"<=" => |l, r| l <= r,
-				">=" => |l, r| l >= r,
-				"<" => |l, r| l < r,
-				">" => |l, r| l > r,
-				_ => return perr!(),
-			};
-			let l = variable::un_char(&parse_or_get(lhs, variables)?)?;
-			let r = variable::un_char(&parse_or_get(rhs, variables)?)?;
-			Ok(Boolean(f(l, r)))
-		}
-		[statement] => parse_or_get(statement, variables),
-		_ => perr!(),
+		_ => terr!(
+			"`{}` is not defined for {}",
+			op,
+			variable::to_type(&operand)
+		),
 	}
 }
+
+pub fn char_op(op: BinaryOp, lhs: Variable, rhs: Variable) -> Result<Variable, CustomErr> {
+	let f = match op {
+		BinaryOp::Eq => |l, r| l == r,
+		BinaryOp::Le => |l, r| l <= r,
+		BinaryOp::Ge => |l, r| l >= r,
+		BinaryOp::Lt => |l, r| l < r,
+		BinaryOp::Gt => |l, r| l > r,
+		_ => return terr!("`{}` is not defined for Char", op),
+	};
+	let l = variable::un_char(&lhs)?;
+	let r = variable::un_char(&rhs)?;
+	Ok(Boolean(f(l, r)))
+}
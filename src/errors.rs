@@ -1,76 +1,165 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{error, fmt};
 
 pub type CustomErr = Box<dyn error::Error>;
 
-#[derive(Copy, Clone, Debug)]
-pub enum CodeError {
-	Parse { line: u32, file: &'static str },
-	Syntax { line: u32, file: &'static str },
-	Type { line: u32, file: &'static str },
+// Byte range into the text of a single statement
+pub type Span = (usize, usize);
+
+// Set by --debug, appends the interpreter source location to every error
+pub static DEBUG: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+	Parse,
+	Syntax,
+	Type,
+	Name,
+	Index,
+	StackOverflow,
+	Arithmetic,
+}
+
+#[derive(Clone, Debug)]
+pub struct CodeError {
+	pub kind: ErrorKind,
+	pub message: Option<String>,
+	pub span: Option<Span>,
+	pub line: u32,
+	pub file: &'static str,
+}
+
+impl fmt::Display for ErrorKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let s = match self {
+			ErrorKind::Parse => "parse",
+			ErrorKind::Syntax => "syntax",
+			ErrorKind::Type => "type",
+			ErrorKind::Name => "name",
+			ErrorKind::Index => "index",
+			ErrorKind::StackOverflow => "stack overflow",
+			ErrorKind::Arithmetic => "arithmetic",
+		};
+		write!(f, "{}", s)
+	}
 }
 
 impl error::Error for CodeError {}
 impl fmt::Display for CodeError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		match self {
-			CodeError::Parse { line: _, file: _ } => write!(f, "parse error!"),
-			CodeError::Syntax { line: _, file: _ } => write!(f, "syntax error!"),
-			CodeError::Type { line: _, file: _ } => write!(f, "type error!"),
+		match &self.message {
+			Some(message) => write!(f, "{} error: {}", self.kind, message)?,
+			None => write!(f, "{} error", self.kind)?,
+		}
+		if DEBUG.load(Ordering::Relaxed) {
+			write!(f, " [{}:{}]", self.file, self.line)?;
 		}
+		Ok(())
 	}
 }
+
+/// Attaches `span` to the error unless a more precise span was already set further down
+pub fn at(mut error: CustomErr, span: Span) -> CustomErr {
+	if let Some(code_error) = error.downcast_mut::<CodeError>() {
+		code_error.span.get_or_insert(span);
+	}
+	error
+}
+
+pub fn span_of(error: &CustomErr) -> Option<Span> {
+	error.downcast_ref::<CodeError>().and_then(|e| e.span)
+}
+
 #[macro_export]
-macro_rules! perr {
-	() => {
-		Err(Box::new(CodeError::Parse {
+macro_rules! code_error {
+	($kind:ident;) => {
+		CodeError {
+			kind: ErrorKind::$kind,
+			message: None,
+			span: None,
 			line: line!(),
 			file: file!(),
-			}))
+		}
+	};
+	($kind:ident; $($arg:tt)+) => {
+		CodeError {
+			kind: ErrorKind::$kind,
+			message: Some(format!($($arg)+)),
+			span: None,
+			line: line!(),
+			file: file!(),
+		}
+	};
+}
+#[macro_export]
+macro_rules! perr {
+	($($arg:tt)*) => {
+		Err(Box::new(code_error!(Parse; $($arg)*)))
 	};
 }
 #[macro_export]
 macro_rules! perrE {
-	() => {
-		CodeError::Parse {
-			line: line!(),
-			file: file!(),
-			}
+	($($arg:tt)*) => {
+		code_error!(Parse; $($arg)*)
 	};
 }
 #[macro_export]
 macro_rules! serr {
-	() => {
-		Err(Box::new(CodeError::Syntax {
-			line: line!(),
-			file: file!(),
-			}))
+	($($arg:tt)*) => {
+		Err(Box::new(code_error!(Syntax; $($arg)*)))
 	};
 }
 #[macro_export]
 macro_rules! serrE {
-	() => {
-		CodeError::Syntax {
-			line: line!(),
-			file: file!(),
-			}
+	($($arg:tt)*) => {
+		code_error!(Syntax; $($arg)*)
 	};
 }
-
 #[macro_export]
 macro_rules! terr {
-	() => {
-		Err(Box::new(CodeError::Type {
-			line: line!(),
-			file: file!(),
-			}))
+	($($arg:tt)*) => {
+		Err(Box::new(code_error!(Type; $($arg)*)))
 	};
 }
 #[macro_export]
 macro_rules! terrE {
-	() => {
-		CodeError::Type {
-			line: line!(),
-			file: file!(),
-			}
+	($($arg:tt)*) => {
+		code_error!(Type; $($arg)*)
+	};
+}
+#[macro_export]
+macro_rules! nerr {
+	($($arg:tt)*) => {
+		Err(Box::new(code_error!(Name; $($arg)*)))
+	};
+}
+#[macro_export]
+macro_rules! nerrE {
+	($($arg:tt)*) => {
+		code_error!(Name; $($arg)*)
+	};
+}
+#[macro_export]
+macro_rules! ierr {
+	($($arg:tt)*) => {
+		Err(Box::new(code_error!(Index; $($arg)*)))
+	};
+}
+#[macro_export]
+macro_rules! ierrE {
+	($($arg:tt)*) => {
+		code_error!(Index; $($arg)*)
+	};
+}
+#[macro_export]
+macro_rules! aerr {
+	($($arg:tt)*) => {
+		Err(Box::new(code_error!(Arithmetic; $($arg)*)))
+	};
+}
+#[macro_export]
+macro_rules! aerrE {
+	($($arg:tt)*) => {
+		code_error!(Arithmetic; $($arg)*)
 	};
 }
@@ -1,9 +1,36 @@
 use crate::*;
 
+use std::fmt;
+use std::rc::Rc;
+
+// (the statement or why it can't be parsed, whether it's the newest line from the REPL, how many
+// lines have been read so far)
+pub type NextLine = (Result<Rc<Statement>, CustomErr>, bool, usize);
+
+#[derive(Clone, Debug, PartialEq)]
+struct Source {
+	path: String,
+	text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+	pub path: String,
+	pub line: usize,
+	pub column: usize,
+	source_line: String,
+	start: usize,
+	len: usize,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Code {
 	code_internal: String,
 	code: Vec<(usize, usize)>,
+	statements: Vec<Option<Rc<Statement>>>,
+	// (index into sources, byte offset of the statement in that source) for every statement
+	origins: Vec<(usize, usize)>,
+	sources: Vec<Source>,
 	pub index: usize,
 }
 
@@ -18,12 +45,21 @@ impl Code {
 		Code {
 			code_internal: String::new(),
 			code: Vec::new(),
+			statements: Vec::new(),
+			origins: Vec::new(),
+			sources: Vec::new(),
 			index: usize::MAX,
 		}
 	}
 
 	pub fn import(&mut self, file: &str) -> Result<(), CustomErr> {
-		let mut file_content = fs::read_to_string(file)?.into_bytes();
+		let text = fs::read_to_string(file)?;
+		self.add_source(file, text)
+	}
+
+	/// Adds the statements of `text` as if they were read from the file at `path`
+	pub fn add_source(&mut self, path: &str, text: String) -> Result<(), CustomErr> {
+		let mut file_content = text.clone().into_bytes();
 		let mut is_comment = false;
 		for byte in file_content.iter_mut() {
 			*byte = match *byte {
@@ -40,45 +76,116 @@ impl Code {
 				c => c,
 			}
 		}
+		// Every byte is replaced one for one, so offsets into file_content are valid in text
 		let file_content = String::from_utf8(file_content)?;
-		for line in file_content.lines() {
-			self.push_line(line);
+		let source = self.sources.len();
+		self.sources.push(Source {
+			path: path.to_owned(),
+			text,
+		});
+		let mut offset = 0;
+		for line in file_content.split('\n') {
+			self.push_line(line, source, offset);
+			offset += line.len() + 1;
 		}
 		Ok(())
 	}
 
-	fn push_line(&mut self, line: &str) {
-		let comment_start = line
-			.chars()
-			.position(|c| c == '#')
-			.unwrap_or_else(|| line.len());
-		let trimmed = (&line[..comment_start]).trim();
+	fn push_line(&mut self, line: &str, source: usize, offset: usize) {
+		let comment_start = line.find('#').unwrap_or(line.len());
+		let trimmed = line[..comment_start].trim();
 		if trimmed.is_empty() {
 			return;
 		}
+		let leading_whitespace = line.len() - line.trim_start().len();
 		let line_start = self.code_internal.len();
 		self.code_internal.push_str(trimmed);
 		self.code.push((line_start, self.code_internal.len()));
+		self.statements.push(None);
+		self.origins.push((source, offset + leading_whitespace));
+	}
+
+	fn read_stdin_line(&mut self) -> Result<(), CustomErr> {
+		let mut input_line = String::new();
+		io::stdin().read_line(&mut input_line)?;
+		let source = match self.sources.iter().position(|s| s.path == "<stdin>") {
+			Some(source) => source,
+			None => {
+				self.sources.push(Source {
+					path: "<stdin>".to_owned(),
+					text: String::new(),
+				});
+				self.sources.len() - 1
+			}
+		};
+		let offset = self.sources[source].text.len();
+		self.sources[source].text.push_str(&input_line);
+		self.push_line(&input_line, source, offset);
+		Ok(())
+	}
+
+	pub fn is_from_stdin(&self, index: usize) -> bool {
+		self.origins
+			.get(index)
+			.and_then(|(source, _)| self.sources.get(*source))
+			.map(|source| source.path == "<stdin>")
+			.unwrap_or(true)
+	}
+
+	/// Maps a span in the statement at `index` back to where it was written.
+	/// Without a span the whole statement is used
+	pub fn location(&self, index: usize, span: Option<Span>) -> Option<Location> {
+		let &(source, offset) = self.origins.get(index)?;
+		let (line_start, line_end) = *self.code.get(index)?;
+		let (span_start, span_end) = span.unwrap_or((0, line_end - line_start));
+		let Source { path, text } = self.sources.get(source)?;
+
+		let start = (offset + span_start).min(text.len());
+		let first = text[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+		let last = text[start..]
+			.find('\n')
+			.map(|i| start + i)
+			.unwrap_or(text.len());
+		let source_line = text[first..last].trim_end_matches('\r');
+		let len = (span_end - span_start).min(source_line.len().saturating_sub(start - first));
+		Some(Location {
+			path: path.clone(),
+			line: text[..start].matches('\n').count() + 1,
+			column: text[first..start].chars().count() + 1,
+			source_line: source_line.to_owned(),
+			start: start - first,
+			len,
+		})
 	}
 
 	fn get_line(&'_ self, index: usize) -> Result<&'_ str, CustomErr> {
 		self.code
 			.get(index)
-			.map(|(s, e)| self.code_internal.get(*s..*e))
-			.flatten()
+			.and_then(|(s, e)| self.code_internal.get(*s..*e))
+			.ok_or_else(|| Box::new(perrE!()) as Box<dyn std::error::Error>)
+	}
+
+	fn get_statement(&mut self, index: usize) -> Result<Rc<Statement>, CustomErr> {
+		if self.statements.get(index).ok_or(perrE!())?.is_none() {
+			let parsed = parser::parse_statement(self.get_line(index)?, index)?;
+			self.statements[index] = Some(Rc::new(parsed));
+		}
+		self.statements[index]
+			.clone()
 			.ok_or_else(|| Box::new(perrE!()) as Box<dyn std::error::Error>)
 	}
 
-	pub fn next_line(&'_ mut self) -> Result<(&'_ str, bool), CustomErr> {
+	/// The next statement, whether it's the newest line from the REPL and how many lines have been
+	/// read so far
+	pub fn next_line(&mut self) -> Result<NextLine, CustomErr> {
 		self.index = self.index.wrapping_add(1);
-		let mut interactive = false;
 		while self.index >= self.code.len() {
-			let mut input_line = String::new();
-			io::stdin().read_line(&mut input_line)?;
-			self.push_line(&input_line);
-			interactive = true;
+			self.read_stdin_line()?;
 		}
-		Ok((self.get_line(self.index)?, interactive))
+		// The newest line from the REPL, whose value is shown
+		let interactive = self.index + 1 == self.code.len() && self.is_from_stdin(self.index);
+		let line_count = self.code.len();
+		Ok((self.get_statement(self.index), interactive, line_count))
 	}
 }
 
@@ -87,3 +194,28 @@ impl Default for Code {
 		Code::new()
 	}
 }
+
+impl fmt::Display for Location {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let gutter = " ".repeat(self.line.to_string().len());
+		// Keep tabs so the carets line up with the source line however tabs are rendered
+		let indent: String = self.source_line[..self.start.min(self.source_line.len())]
+			.chars()
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect();
+		let width = self
+			.source_line
+			.get(self.start..self.start + self.len)
+			.map(|s| s.chars().count())
+			.unwrap_or(0)
+			.max(1);
+		writeln!(
+			f,
+			"{}--> {}:{}:{}",
+			gutter, self.path, self.line, self.column
+		)?;
+		writeln!(f, "{} |", gutter)?;
+		writeln!(f, "{} | {}", self.line, self.source_line)?;
+		write!(f, "{} | {}{}", gutter, indent, "^".repeat(width))
+	}
+}
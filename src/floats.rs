@@ -1,159 +1,292 @@
 use crate::*;
 
-#[derive(Clone, Debug, PartialEq)]
-enum Op<'a> {
-	Add(Box<Op<'a>>, Box<Op<'a>>),
-	Sub(Box<Op<'a>>, Box<Op<'a>>),
-	Mul(Box<Op<'a>>, Box<Op<'a>>),
-	Div(Box<Op<'a>>, Box<Op<'a>>),
-	Mod(Box<Op<'a>>, Box<Op<'a>>),
-	Pow(Box<Op<'a>>, Box<Op<'a>>),
-	Val(Variable),
-	Unparsed(&'a str),
-}
-use Op::*;
-type OpFnPtr<'a> = fn(Box<Op<'a>>, Box<Op<'a>>) -> Op<'a>;
+use std::convert::TryFrom;
 
-fn evaluate_float(num: &str) -> Result<Variable, CustomErr> {
-	if !num.bytes().all(|x| x.is_ascii_digit() || x == b'.') {
-		return perr!();
+/// Parses `inf`, `nan` and decimal literals like `1_000.5` or `2.5e-3` into the closest f64
+pub fn evaluate_float(num: &str) -> Result<f64, CustomErr> {
+	match num {
+		"inf" => return Ok(f64::INFINITY),
+		"nan" => return Ok(f64::NAN),
+		_ => {}
 	}
-	let mut splits = num.split('.');
-	match (splits.next(), splits.next(), splits.next()) {
-		(Some(_), None, None) => Ok(Number(parse_int(num) as f64)),
-		(Some(_), Some(_), None) => {
-			let number = parse_int(num) as f64;
-			let dot_index = num.bytes().rev().position(|c| c == b'.').unwrap_or(0) as i32;
-			Ok(Number(number / 10f64.powi(dot_index)))
-		}
-		_ => perr!(),
+	let (mantissa, exponent) = match num.find(['e', 'E']) {
+		Some(i) => (&num[..i], Some(&num[i + 1..])),
+		None => (num, None),
+	};
+	let whole = |s: &str| -> Result<bool, CustomErr> {
+		let digits = without_separators(s, 10)?;
+		Ok(!digits.is_empty() && digits.bytes().all(|x| x.is_ascii_digit()))
+	};
+	let valid_mantissa = match mantissa.split_once('.') {
+		Some((int, fract)) => whole(int)? && whole(fract)?,
+		None => whole(mantissa)?,
+	};
+	let valid_exponent = match exponent {
+		Some(exponent) => whole(exponent.trim_start_matches(['+', '-']))?,
+		None => true,
+	};
+	if !valid_mantissa || !valid_exponent {
+		return perr!("invalid number literal `{}`", num);
 	}
+	let parsed = num
+		.replace('_', "")
+		.parse::<f64>()
+		.map_err(|_| perrE!("invalid number literal `{}`", num))?;
+	if parsed.is_infinite() {
+		return perr!("`{}` is too large for a Number", num);
+	}
+	Ok(parsed)
 }
 
-fn parse_int(string: &str) -> u64 {
-	string
-		.bytes()
-		.filter(u8::is_ascii_digit)
-		.fold(0, |acc, curr| acc * 10 + (curr - b'0') as u64)
-}
-
-fn get_left_and_right<'a>(
-	idx: &mut usize,
-	words: &mut Vec<Op<'a>>,
-	variables: &Variables,
-) -> Result<(Op<'a>, Op<'a>), CustomErr> {
-	if *idx == 0 {
-		return perr!();
+/// The shortest text that `evaluate_float` reads back as the same Number, with a `.0` on whole
+/// numbers so that they aren't read as Ints
+pub fn display(n: f64) -> String {
+	if n.is_nan() {
+		"nan".to_owned()
+	} else {
+		// Debug rather than Display switches to an exponent for very large or small numbers
+		format!("{:?}", n)
 	}
-	let left = match words.remove(*idx - 1) {
-		Unparsed(s) => Val(parse_or_get(s, variables)?),
-		x => x,
-	};
-	*idx -= 1;
-	let right = match words.remove(*idx + 1) {
-		Unparsed(s) => Val(parse_or_get(s, variables)?),
-		x => x,
-	};
-	Ok((left, right))
 }
 
-pub fn parse_or_get(s: &str, variables: &Variables) -> Result<Variable, CustomErr> {
-	let val = if helper::has_parentheses(s) {
-		variable::evaluate_statement(&helper::split(helper::remove_parentheses(s))?, variables)?
-	} else if let Some(n) = variables.get(s) {
-		n.clone()
-	} else if let Ok(n) = evaluate_float(s) {
-		n
-	} else {
-		return perr!();
+/// Parses decimal literals and hexadecimal, binary or octal ones starting with `0x`, `0b` or `0o`.
+/// One past i64::MAX is read as i64::MIN, which the parser only accepts right after a `-`
+pub fn evaluate_int(num: &str) -> Result<i64, CustomErr> {
+	let (radix, digits) = match num.get(..2) {
+		Some("0x") => (16, &num[2..]),
+		Some("0b") => (2, &num[2..]),
+		Some("0o") => (8, &num[2..]),
+		_ => (10, num),
 	};
-	variable::assert_type_of(&val, &NumberT)?;
-	Ok(val)
+	let digits = without_separators(digits, radix)?;
+	if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+		return perr!("invalid number literal `{}`", num);
+	}
+	digits
+		.chars()
+		.try_fold(0u64, |acc, c| {
+			acc.checked_mul(radix as u64)?
+				.checked_add(c.to_digit(radix)? as u64)
+		})
+		.and_then(|n| match i64::try_from(n) {
+			Ok(n) => Some(n),
+			Err(_) if n == i64::MIN.unsigned_abs() => Some(i64::MIN),
+			Err(_) => None,
+		})
+		.ok_or_else(|| perrE!("`{}` is too large for an Int", num).into())
 }
 
-fn eval_op(op: Op, variables: &Variables) -> Result<f64, CustomErr> {
-	Ok(match op {
-		Add(l, r) => eval_op(*l, variables)? + eval_op(*r, variables)?,
-		Sub(l, r) => eval_op(*l, variables)? - eval_op(*r, variables)?,
-		Mul(l, r) => eval_op(*l, variables)? * eval_op(*r, variables)?,
-		Div(l, r) => eval_op(*l, variables)? / eval_op(*r, variables)?,
-		Mod(l, r) => eval_op(*l, variables)? % eval_op(*r, variables)?,
-		Pow(l, r) => eval_op(*l, variables)?.powf(eval_op(*r, variables)?),
-		Val(Number(x)) => x,
-		Unparsed(s) => variable::un_number(&parse_or_get(s, variables)?)?,
-		_ => return perr!(),
-	})
+// Removes the `_`s from a literal like `1_000_000`, each of which has to be between two digits
+fn without_separators(num: &str, radix: u32) -> Result<String, CustomErr> {
+	let chars = num.chars().collect::<Vec<_>>();
+	for (i, c) in chars.iter().enumerate() {
+		let digit = |j: Option<usize>| {
+			j.and_then(|j| chars.get(j))
+				.is_some_and(|c| c.is_digit(radix))
+		};
+		if *c == '_' && !(digit(i.checked_sub(1)) && digit(Some(i + 1))) {
+			return perr!("`_` in `{}` has to be between two digits", num);
+		}
+	}
+	Ok(chars.into_iter().filter(|c| *c != '_').collect())
 }
 
-fn perform_all_of_operation<'a>(
-	words: &mut Vec<Op<'a>>,
-	variables: &Variables,
-	operator: &str,
-	operation_function: OpFnPtr<'a>,
-) -> Result<(), CustomErr> {
-	while let Some(mut idx) = words.iter().position(|x| *x == Unparsed(operator)) {
-		let (left, right) = get_left_and_right(&mut idx, words, variables)?;
-		words[idx] = operation_function(Box::new(left), Box::new(right));
+/// Arithmetic and comparisons on two numbers of the same kind
+pub fn number_op(op: BinaryOp, lhs: Variable, rhs: Variable) -> Result<Variable, CustomErr> {
+	match (&lhs, &rhs) {
+		(Int(l), Int(r)) => int_op(op, *l, *r),
+		(Int(_), Number(_)) | (Number(_), Int(_)) => terr!(
+			"`{}` is not defined for {} and {}, convert one of them with `int` or `float`",
+			op,
+			variable::to_type(&lhs),
+			variable::to_type(&rhs)
+		),
+		(Int(_), _) => terr!("expected Int, found {}", variable::to_type(&rhs)),
+		_ => float_op(op, lhs, rhs),
 	}
-	Ok(())
 }
 
-fn order_of_operations_parse(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	let mut words: Vec<Op> = words.iter().map(|x| Unparsed(x)).collect();
+// Division and modulo round towards zero. Results that don't fit in an Int are errors rather than
+// wrapping around
+fn int_op(op: BinaryOp, l: i64, r: i64) -> Result<Variable, CustomErr> {
+	let checked = |res: Option<i64>| {
+		res.map(Int)
+			.ok_or_else(|| aerrE!("`{} {} {}` doesn't fit in an Int", l, op, r))
+	};
+	let res = match op {
+		BinaryOp::Add => checked(l.checked_add(r))?,
+		BinaryOp::Sub => checked(l.checked_sub(r))?,
+		BinaryOp::Mul => checked(l.checked_mul(r))?,
+		BinaryOp::Div | BinaryOp::Mod if r == 0 => return aerr!("division by zero"),
+		BinaryOp::Div => checked(l.checked_div(r))?,
+		BinaryOp::Mod => checked(l.checked_rem(r))?,
+		BinaryOp::Pow => match u32::try_from(r) {
+			Ok(r) => checked(l.checked_pow(r))?,
+			Err(_) => {
+				return aerr!(
+					"can't raise an Int to the power of {}, convert it with `float` first",
+					r
+				)
+			}
+		},
+		BinaryOp::Eq => Boolean(l == r),
+		BinaryOp::Lt => Boolean(l < r),
+		BinaryOp::Le => Boolean(l <= r),
+		BinaryOp::Gt => Boolean(l > r),
+		BinaryOp::Ge => Boolean(l >= r),
+		_ => return terr!("`{}` is not defined for Int", op),
+	};
+	Ok(res)
+}
+
+fn float_op(op: BinaryOp, lhs: Variable, rhs: Variable) -> Result<Variable, CustomErr> {
+	let l = variable::un_number(&lhs)?;
+	let r = variable::un_number(&rhs)?;
+	let res = match op {
+		BinaryOp::Add => Number(l + r),
+		BinaryOp::Sub => Number(l - r),
+		BinaryOp::Mul => Number(l * r),
+		BinaryOp::Div => Number(l / r),
+		BinaryOp::Mod => Number(l % r),
+		BinaryOp::Pow => Number(l.powf(r)),
+		BinaryOp::Eq => Boolean((l - r).abs() < f64::EPSILON),
+		BinaryOp::Lt => Boolean(l < r),
+		BinaryOp::Le => Boolean(l <= r),
+		BinaryOp::Gt => Boolean(l > r),
+		BinaryOp::Ge => Boolean(l >= r),
+		_ => return terr!("`{}` is not defined for Number", op),
+	};
+	Ok(res)
+}
 
-	let operator_fn_pair: [(&str, OpFnPtr); 6] = [
-		("^", |lhs, rhs| Pow(lhs, rhs)),
-		("*", |lhs, rhs| Mul(lhs, rhs)),
-		("/", |lhs, rhs| Div(lhs, rhs)),
-		("%", |lhs, rhs| Mod(lhs, rhs)),
-		("+", |lhs, rhs| Add(lhs, rhs)),
-		("-", |lhs, rhs| Sub(lhs, rhs)),
-	];
-	for (operator, node_type) in operator_fn_pair.iter() {
-		perform_all_of_operation(&mut words, variables, operator, *node_type)?;
+pub fn negate(operand: Variable) -> Result<Variable, CustomErr> {
+	match operand {
+		Int(n) => n
+			.checked_neg()
+			.map(Int)
+			.ok_or_else(|| aerrE!("-({}) doesn't fit in an Int", n).into()),
+		_ => Ok(Number(-variable::un_number(&operand)?)),
 	}
+}
 
-	if words.len() != 1 {
-		return perr!();
+/// The number after `n` when counting up in a loop
+pub fn successor(n: &Variable) -> Result<Variable, CustomErr> {
+	match n {
+		Int(n) => n
+			.checked_add(1)
+			.map(Int)
+			.ok_or_else(|| aerrE!("{} + 1 doesn't fit in an Int", n).into()),
+		_ => Ok(Number(variable::un_number(n)? + 1.)),
 	}
-
-	Ok(Number(eval_op(words.remove(0), variables)?))
 }
 
-fn logic_parse(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	if words.len() != 3 {
-		return perr!();
-	}
-	let op = words[1];
-	let f: fn(f64, f64) -> bool = match op {
-		"==" => |l, r| (l - r).abs() < f64::EPSILON,
-		"<=" => |l, r| l <= r,
-		">=" => |l, r| l >= r,
-		"<" => |l, r| l < r,
-		">" => |l, r| l > r,
-		_ => return perr!(),
+/// `pi`, `e` and `tau`
+pub fn constant(name: &str) -> Option<Variable> {
+	let value = match name {
+		"pi" => std::f64::consts::PI,
+		"e" => std::f64::consts::E,
+		"tau" => std::f64::consts::TAU,
+		_ => return None,
 	};
-	let lhs = variable::un_number(&variable::evaluate_statement(&words[0..1], variables)?)?;
-	let rhs = variable::un_number(&variable::evaluate_statement(&words[2..3], variables)?)?;
-
-	Ok(Boolean(f(lhs, rhs)))
+	Some(Number(value))
 }
 
-fn round_parse(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	if words.len() != 2 {
-		return perr!();
+pub fn round_op(op: UnaryOp, operand: Variable) -> Result<Variable, CustomErr> {
+	if let Int(n) = operand {
+		return match op {
+			// An Int is already whole
+			UnaryOp::Floor | UnaryOp::Ceil | UnaryOp::Round | UnaryOp::Trunc => Ok(operand),
+			UnaryOp::Abs => n
+				.checked_abs()
+				.map(Int)
+				.ok_or_else(|| aerrE!("abs {} doesn't fit in an Int", n).into()),
+			UnaryOp::Sign => Ok(Int(n.signum())),
+			_ => terr!(
+				"`{}` is not defined for Int, convert it with `float` first",
+				op
+			),
+		};
 	}
-	let f: fn(f64) -> f64 = match words[0] {
-		"floor" => |x| x.floor(),
-		"ceil" => |x| x.ceil(),
-		"round" => |x| x.round(),
-		"sqrt" => |x| x.sqrt(),
-		_ => return perr!(),
+	let f: fn(f64) -> f64 = match op {
+		UnaryOp::Floor => |x| x.floor(),
+		UnaryOp::Ceil => |x| x.ceil(),
+		UnaryOp::Round => |x| x.round(),
+		UnaryOp::Sqrt => |x| x.sqrt(),
+		UnaryOp::Abs => |x| x.abs(),
+		UnaryOp::Trunc => |x| x.trunc(),
+		// Unlike `signum`, 0 has no sign
+		UnaryOp::Sign => |x| if x == 0. { x } else { x.signum() },
+		UnaryOp::Sin => |x| x.sin(),
+		UnaryOp::Cos => |x| x.cos(),
+		UnaryOp::Tan => |x| x.tan(),
+		UnaryOp::Asin => |x| x.asin(),
+		UnaryOp::Acos => |x| x.acos(),
+		UnaryOp::Atan => |x| x.atan(),
+		UnaryOp::Ln => |x| x.ln(),
+		UnaryOp::Log10 => |x| x.log10(),
+		UnaryOp::Log2 => |x| x.log2(),
+		UnaryOp::Exp => |x| x.exp(),
+		_ => return terr!("`{}` is not defined for Number", op),
 	};
-	let num = variable::un_number(&variable::evaluate_statement(&words[1..2], variables)?)?;
+	let num = variable::un_number(&operand)?;
 	Ok(Number(f(num)))
 }
 
-pub fn evaluate_floats(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	logic_parse(words, variables).or_else(|_| round_parse(words, variables)).or_else(|_| order_of_operations_parse(words, variables))
+/// `int` rounds a Number towards zero, `float` turns an Int into a Number
+pub fn convert_op(op: UnaryOp, operand: Variable) -> Result<Variable, CustomErr> {
+	match (op, operand) {
+		(UnaryOp::ToInt, Number(n)) => {
+			let n = n.trunc();
+			// i64::MAX isn't exactly representable, it rounds up to 2^63
+			if n.is_nan() || n < i64::MIN as f64 || n >= i64::MAX as f64 {
+				aerr!("{} doesn't fit in an Int", display(n))
+			} else {
+				Ok(Int(n as i64))
+			}
+		}
+		(UnaryOp::ToFloat, Int(n)) => Ok(Number(n as f64)),
+		(UnaryOp::ToInt, n @ Int(_)) | (UnaryOp::ToFloat, n @ Number(_)) => Ok(n),
+		(op, operand) => terr!(
+			"`{}` is not defined for {}",
+			op,
+			variable::to_type(&operand)
+		),
+	}
+}
+
+/// `atan2(y x)`, `hypot(x y)`, `log(x base)` and `clamp(x min max)`, whose arguments were already
+/// counted
+pub fn builtin(builtin: Builtin, args: Vec<Variable>) -> Result<Variable, CustomErr> {
+	if let (Builtin::Clamp, [Int(x), Int(min), Int(max)]) = (builtin, args.as_slice()) {
+		if min > max {
+			return serr!(
+				"`clamp` needs a minimum no larger than the maximum, found {} and {}",
+				min,
+				max
+			);
+		}
+		return Ok(Int(*x.clamp(min, max)));
+	}
+	let args = args
+		.iter()
+		.map(variable::un_number)
+		.collect::<Result<Vec<_>, _>>()?;
+	let res = match (builtin, args.as_slice()) {
+		(Builtin::Atan2, [y, x]) => y.atan2(*x),
+		(Builtin::Hypot, [x, y]) => x.hypot(*y),
+		(Builtin::Log, [x, base]) => x.log(*base),
+		(Builtin::Clamp, [x, min, max]) => {
+			// `f64::clamp` panics on these
+			if min > max || min.is_nan() || max.is_nan() {
+				return serr!(
+					"`clamp` needs a minimum no larger than the maximum, found {} and {}",
+					display(*min),
+					display(*max)
+				);
+			}
+			x.clamp(*min, *max)
+		}
+		_ => return serr!(),
+	};
+	Ok(Number(res))
 }
@@ -0,0 +1,150 @@
+use crate::*;
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+	Number(f64),
+	Int(i64),
+	Char(char),
+	Str(String),
+	Word(String),
+	Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Token::Number(n) => write!(f, "{}", floats::display(*n)),
+			Token::Int(n) => write!(f, "{}", n),
+			Token::Char(c) => write!(f, "'{}'", c),
+			Token::Str(s) => write!(f, "{:?}", s),
+			Token::Word(w) => write!(f, "{}", w),
+			Token::Symbol(s) => write!(f, "{}", s),
+		}
+	}
+}
+
+// Longest symbols first so that `++` isn't read as two `+`
+const SYMBOLS: [&str; 24] = [
+	"..=", "++", "..", "->", "==", "<=", ">=", "+", "-", "*", "/", "%", "^", "@", "!", "=", "<",
+	">", "&", "|", "(", ")", "[", "]",
+];
+
+pub fn tokenize(s: &str) -> Result<Vec<(Token, Span)>, CustomErr> {
+	let mut tokens = Vec::new();
+	let mut rest = s.trim_start();
+	while let Some(c) = rest.chars().next() {
+		let start = s.len() - rest.len();
+		let (token, len) = if c.is_ascii_digit() {
+			lex_number(rest)
+		} else if c.is_alphabetic() || c == '_' {
+			Ok(lex_word(rest))
+		} else if c == '\'' {
+			lex_char(rest)
+		} else if c == '"' {
+			lex_string(rest)
+		} else {
+			lex_symbol(rest)
+		}
+		.map_err(|e| errors::at(e, (start, start + 1)))?;
+		tokens.push((token, (start, start + len)));
+		rest = rest[len..].trim_start();
+	}
+	Ok(tokens)
+}
+
+fn lex_number(s: &str) -> Result<(Token, usize), CustomErr> {
+	// `0x1F`, `0b101` and `0o17`
+	if s.starts_with("0x") || s.starts_with("0b") || s.starts_with("0o") {
+		let len = 2 + s[2..]
+			.bytes()
+			.take_while(|c| c.is_ascii_alphanumeric() || *c == b'_')
+			.count();
+		return Ok((Token::Int(floats::evaluate_int(&s[..len])?), len));
+	}
+	let digits = |s: &str| {
+		s.bytes()
+			.take_while(|c| c.is_ascii_digit() || *c == b'_')
+			.count()
+	};
+	let starts_digit = |s: &str| s.bytes().next().is_some_and(|c| c.is_ascii_digit());
+	let mut len = digits(s);
+	let mut float = false;
+	// A fraction needs digits so that `0..5` is a range
+	if s[len..].starts_with('.') && starts_digit(&s[len + 1..]) {
+		len += 1 + digits(&s[len + 1..]);
+		float = true;
+	}
+	// As does an exponent, so that `2e` is a number followed by a name
+	if s[len..].starts_with('e') || s[len..].starts_with('E') {
+		let rest = &s[len + 1..];
+		let sign = usize::from(rest.starts_with('+') || rest.starts_with('-'));
+		if starts_digit(&rest[sign..]) {
+			len += 1 + sign + digits(&rest[sign..]);
+			float = true;
+		}
+	}
+	if !float {
+		return Ok((Token::Int(floats::evaluate_int(&s[..len])?), len));
+	}
+	Ok((Token::Number(floats::evaluate_float(&s[..len])?), len))
+}
+
+fn lex_word(s: &str) -> (Token, usize) {
+	let len = s
+		.char_indices()
+		.find(|(_, c)| !c.is_alphanumeric() && *c != '_')
+		.map(|(i, _)| i)
+		.unwrap_or(s.len());
+	(Token::Word(s[..len].to_owned()), len)
+}
+
+fn unescape(c: char) -> char {
+	match c {
+		'n' => '\n',
+		't' => '\t',
+		c => c,
+	}
+}
+
+fn lex_char(s: &str) -> Result<(Token, usize), CustomErr> {
+	let mut chars = s.char_indices().skip(1);
+	let c = match chars.next() {
+		Some((_, '\\')) => chars.next().map(|(_, c)| unescape(c)),
+		c => c.map(|(_, c)| c),
+	};
+	match (c, chars.next()) {
+		(Some(c), Some((i, '\''))) => Ok((Token::Char(c), i + 1)),
+		_ => perr!("unterminated character literal"),
+	}
+}
+
+fn lex_string(s: &str) -> Result<(Token, usize), CustomErr> {
+	let mut string = String::new();
+	let mut chars = s.char_indices().skip(1);
+	while let Some((i, c)) = chars.next() {
+		match c {
+			'"' => return Ok((Token::Str(string), i + 1)),
+			'\\' => string.push(
+				chars
+					.next()
+					.map(|(_, c)| unescape(c))
+					.ok_or(perrE!("unterminated string literal"))?,
+			),
+			c => string.push(c),
+		}
+	}
+	perr!("unterminated string literal")
+}
+
+fn lex_symbol(s: &str) -> Result<(Token, usize), CustomErr> {
+	SYMBOLS
+		.iter()
+		.find(|symbol| s.starts_with(*symbol))
+		.map(|symbol| (Token::Symbol(symbol), symbol.len()))
+		.ok_or_else(|| {
+			let c = s.chars().next().unwrap_or(' ');
+			Box::new(perrE!("unexpected character `{}`", c)) as Box<dyn std::error::Error>
+		})
+}
@@ -1,124 +1,697 @@
 use crate::*;
 
-fn evaluate_list(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	if words.len() != 1 {
-		return perr!();
-	}
-	let word = words[0];
-	if !helper::is_list(word) {
-		return perr!();
-	}
+use std::cmp::Ordering;
+use std::rc::Rc;
 
-	let mut vec = Vec::new();
+pub fn evaluate_list(items: &[Expr], ctx: &mut dyn Context) -> Result<Variable, CustomErr> {
+	let mut vec = Vec::with_capacity(items.len());
 	let typ;
-	let split = helper::split(helper::remove_parentheses(word))?;
-	let mut iter = split.iter();
-	if let Some(&var) = iter.next() {
-		let parsed = variable::evaluate_statement(&helper::split(var)?, variables)?;
+	let mut iter = items.iter();
+	if let Some(item) = iter.next() {
+		let parsed = variable::evaluate_expression(item, ctx)?;
 		typ = variable::to_type(&parsed);
 		vec.push(parsed);
 	} else {
-		return perr!();
+		return perr!("can't infer the type of an empty list");
 	}
-	for &token in iter {
-		let parsed = variable::evaluate_statement(&helper::split(token)?, variables)?;
+	for item in iter {
+		let parsed = variable::evaluate_expression(item, ctx)?;
 		variable::assert_type_of(&parsed, &typ)?;
 		vec.push(parsed);
 	}
-	Ok(List(typ, vec))
-}
-
-fn evaluate_string(word: &str) -> Result<Variable, CustomErr> {
-	if helper::is_string(word) {
-		Ok(List(
-			CharT,
-			helper::remove_parentheses(word).chars().map(Char).collect(),
-		))
-	} else {
-		perr!()
-	}
+	Ok(List(typ, Rc::new(vec)))
 }
 
+// `bound` allows the index to be the length of the list, for inserting at the end
 fn parse_list_and_index(
 	list: Variable,
 	index: Variable,
-) -> Result<(VariableT, Vec<Variable>, usize), CustomErr> {
+	bound: bool,
+) -> Result<(VariableT, Rc<Vec<Variable>>, usize), CustomErr> {
 	let (typ, vec) = variable::un_list(list)?;
-	let i = variable::un_number(&index)? as usize;
-	if vec.len() >= i {
-		Ok((typ, vec, i))
-	} else {
-		eprintln!("Out of bounds");
-		serr!()
-	}
+	let i = position(&index, vec.len(), "index", bound)?;
+	Ok((typ, vec, i))
 }
 
 fn remove_from_list(list: Variable, index: Variable) -> Result<Variable, CustomErr> {
-	let (t, mut vec, index) = parse_list_and_index(list, index)?;
-	vec.remove(index);
+	let (t, mut vec, index) = parse_list_and_index(list, index, false)?;
+	Rc::make_mut(&mut vec).remove(index);
 	Ok(List(t, vec))
 }
 
-fn add_to_list(list: Variable, index: Variable, item: Variable) -> Result<Variable, CustomErr> {
-	let (t, mut vec, index) = parse_list_and_index(list, index)?;
+pub fn add_to_list(list: Variable, index: Variable, item: Variable) -> Result<Variable, CustomErr> {
+	let (t, mut vec, index) = parse_list_and_index(list, index, true)?;
 	variable::assert_type_of(&item, &t)?;
-	vec.insert(index, item);
+	Rc::make_mut(&mut vec).insert(index, item);
 	Ok(List(t, vec))
 }
 
-fn list_len(list: &Variable) -> Result<usize, CustomErr> {
+/// Adds `item` to the end of `list`, only copying the items if they are shared with another list
+pub fn push_to_list(list: &mut Variable, item: Variable) -> Result<(), CustomErr> {
+	match list {
+		List(t, vec) => {
+			variable::assert_type_of(&item, t)?;
+			Rc::make_mut(vec).push(item);
+			Ok(())
+		}
+		_ => terr!("expected List, found {}", variable::to_type(list)),
+	}
+}
+
+/// Adds the items of `other` to the end of `list`, only copying `list` if it is shared
+pub fn join_into_list(list: &mut Variable, other: Variable) -> Result<(), CustomErr> {
+	let (typ_r, list_r) = variable::un_list(other)?;
+	match list {
+		List(typ_l, vec) => {
+			variable::assert_type(typ_l, &typ_r)?;
+			Rc::make_mut(vec).extend(list_r.iter().cloned());
+			Ok(())
+		}
+		_ => terr!("expected List, found {}", variable::to_type(list)),
+	}
+}
+
+pub fn list_len(list: &Variable) -> Result<usize, CustomErr> {
 	if let List(_, l) = list {
 		Ok(l.len())
 	} else {
-		terr!()
+		terr!("expected List, found {}", variable::to_type(list))
 	}
 }
 
-fn join_lists(lhs: Variable, rhs: Variable) -> Result<Variable, CustomErr> {
-	let (typ_l, mut list_l) = variable::un_list(lhs)?;
-	let (typ_r, mut list_r) = variable::un_list(rhs)?;
-	variable::assert_type(&typ_l, &typ_r)?;
-	list_l.append(&mut list_r);
-	Ok(List(typ_l, list_l))
+fn get_item(list: Variable, index: Variable) -> Result<Variable, CustomErr> {
+	let (_, vec, index) = parse_list_and_index(list, index, false)?;
+	Ok(vec[index].clone())
 }
 
-fn get_item(list: Variable, index: Variable) -> Result<Variable, CustomErr> {
-	let (_, mut vec, index) = parse_list_and_index(list, index)?;
-	Ok(vec.remove(index))
+pub fn list_op(op: BinaryOp, mut lhs: Variable, rhs: Variable) -> Result<Variable, CustomErr> {
+	let val = match op {
+		BinaryOp::Add => {
+			push_to_list(&mut lhs, rhs)?;
+			lhs
+		}
+		BinaryOp::Sub => remove_from_list(lhs, rhs)?,
+		BinaryOp::Join => {
+			join_into_list(&mut lhs, rhs)?;
+			lhs
+		}
+		BinaryOp::Index => get_item(lhs, rhs)?,
+		_ => return terr!("`{}` is not defined for {}", op, variable::to_type(&lhs)),
+	};
+	Ok(val)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Takes {
+	Values(usize),
+	// The values followed by a function or operator
+	Callback(usize),
+	// The values, optionally followed by a function or operator
+	OptionalCallback(usize),
+}
+
+fn arity(builtin: Builtin) -> Takes {
+	match builtin {
+		Builtin::Sort | Builtin::Reverse => Takes::Values(1),
+		Builtin::Map | Builtin::Filter | Builtin::Reduce | Builtin::SortBy | Builtin::Find => {
+			Takes::Callback(1)
+		}
+		Builtin::Fold => Takes::Callback(2),
+		Builtin::Any | Builtin::All | Builtin::Enumerate => Takes::OptionalCallback(1),
+		Builtin::Zip => Takes::OptionalCallback(2),
+		Builtin::Min | Builtin::Max => Takes::Values(1),
+		Builtin::Atan2 | Builtin::Hypot | Builtin::Log => Takes::Values(2),
+		Builtin::Clamp => Takes::Values(3),
+	}
+}
+
+// What a built-in calls for each item
+enum Callback {
+	Function(Rc<Closure>),
+	Operator(BinaryOp),
+}
+
+/// Runs a built-in, calling back the script function it may be given for the items
+pub fn builtin(
+	builtin: Builtin,
+	mut args: Vec<Variable>,
+	operator: Option<BinaryOp>,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let count = args.len() + usize::from(operator.is_some());
+	let (values, min, max) = match arity(builtin) {
+		Takes::Values(n) => (n, n, n),
+		Takes::Callback(n) => (n, n + 1, n + 1),
+		Takes::OptionalCallback(n) => (n, n, n + 1),
+	};
+	if count < min || count > max || (operator.is_some() && args.len() != values) {
+		let expected = if min == max {
+			min.to_string()
+		} else {
+			format!("{} to {}", min, max)
+		};
+		return terr!(
+			"wrong number of arguments to `{}`: expected {}, found {}",
+			builtin,
+			expected,
+			count
+		);
+	}
+	let callback = match operator {
+		Some(op) => Some(Callback::Operator(op)),
+		None if args.len() > values => {
+			let function = args.pop().ok_or(serrE!())?;
+			Some(Callback::Function(variable::un_function(&function)?))
+		}
+		None => None,
+	};
+	if let Builtin::Atan2 | Builtin::Hypot | Builtin::Log | Builtin::Clamp = builtin {
+		return floats::builtin(builtin, args);
+	}
+	let mut args = args.into_iter();
+	let list = args.next().ok_or(serrE!())?;
+	let other = args.next();
+	match builtin {
+		Builtin::Map => map(list, function(builtin, callback)?, ctx),
+		Builtin::Filter => filter(list, function(builtin, callback)?, ctx),
+		Builtin::Fold => {
+			let init = other.ok_or(serrE!())?;
+			fold(list, init, callback.ok_or(serrE!())?, ctx)
+		}
+		Builtin::Reduce => reduce(list, callback.ok_or(serrE!())?, ctx),
+		Builtin::Sort => sort(list),
+		Builtin::SortBy => sort_by(list, callback.ok_or(serrE!())?, ctx),
+		Builtin::Reverse => {
+			let (typ, mut items) = variable::un_list(list)?;
+			Rc::make_mut(&mut items).reverse();
+			Ok(List(typ, items))
+		}
+		Builtin::Find => find(list, function(builtin, callback)?, ctx),
+		Builtin::Any | Builtin::All => {
+			let function = callback.map(|c| function(builtin, Some(c))).transpose()?;
+			any_or_all(builtin == Builtin::Any, list, function, ctx)
+		}
+		Builtin::Zip => zip(list, other.ok_or(serrE!())?, callback, ctx),
+		Builtin::Enumerate => enumerate(list, callback, ctx),
+		Builtin::Min | Builtin::Max => extreme(builtin == Builtin::Max, list),
+		Builtin::Atan2 | Builtin::Hypot | Builtin::Log | Builtin::Clamp => serr!(),
+	}
+}
+
+// Calls `function` with each item on its own
+fn each(
+	items: &[Variable],
+	function: &Rc<Closure>,
+	ctx: &mut dyn Context,
+) -> Result<Vec<Variable>, CustomErr> {
+	items
+		.iter()
+		.map(|item| ctx.call(function, vec![item.clone()]))
+		.collect()
+}
+
+// Calls `function` with the value so far and each item in turn, what it returns being the next
+// value so far
+fn accumulate(
+	items: &[Variable],
+	init: Variable,
+	function: &Rc<Closure>,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	items.iter().try_fold(init, |acc, item| {
+		ctx.call(function, vec![acc, item.clone()])
+	})
+}
+
+// Only callbacks that combine two values can be operators
+fn function(builtin: Builtin, callback: Option<Callback>) -> Result<Rc<Closure>, CustomErr> {
+	match callback {
+		Some(Callback::Function(function)) => Ok(function),
+		Some(Callback::Operator(op)) => terr!("`{}` needs a function, found `{}`", builtin, op),
+		None => Err(Box::new(serrE!())),
+	}
+}
+
+// A list of what a callback returned, typed by its return type if there are no items
+fn typed_list(
+	items: Vec<Variable>,
+	returns: Option<VariableT>,
+	builtin: Builtin,
+) -> Result<Variable, CustomErr> {
+	let typ = match (items.first(), returns) {
+		(Some(first), _) => variable::to_type(first),
+		(None, Some(returns)) => returns,
+		(None, None) => {
+			return terr!(
+				"can't infer the type of `{}` over an empty list, give the function a return type",
+				builtin
+			)
+		}
+	};
+	for item in items.iter() {
+		variable::assert_type_of(item, &typ)?;
+	}
+	Ok(List(typ, Rc::new(items)))
+}
+
+fn map(
+	list: Variable,
+	function: Rc<Closure>,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (_, items) = variable::un_list(list)?;
+	let results = each(&items, &function, ctx)?;
+	typed_list(results, function.returns.clone(), Builtin::Map)
+}
+
+fn filter(
+	list: Variable,
+	function: Rc<Closure>,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (typ, items) = variable::un_list(list)?;
+	let mut result = Vec::new();
+	for item in items.iter() {
+		if variable::un_bool(&ctx.call(&function, vec![item.clone()])?)? {
+			result.push(item.clone());
+		}
+	}
+	Ok(List(typ, Rc::new(result)))
+}
+
+fn fold(
+	list: Variable,
+	init: Variable,
+	callback: Callback,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (_, items) = variable::un_list(list)?;
+	match callback {
+		Callback::Operator(op) => items
+			.iter()
+			.try_fold(init, |acc, item| variable::binary_op(op, acc, item.clone())),
+		Callback::Function(function) => accumulate(&items, init, &function, ctx),
+	}
 }
 
-pub fn list_op(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	if words.is_empty() {
-		return serr!();
+fn reduce(
+	list: Variable,
+	callback: Callback,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (_, items) = variable::un_list(list)?;
+	let first = items
+		.first()
+		.cloned()
+		.ok_or_else(|| ierrE!("can't reduce an empty list"))?;
+	match callback {
+		Callback::Operator(op) => items[1..].iter().try_fold(first, |acc, item| {
+			variable::binary_op(op, acc, item.clone())
+		}),
+		Callback::Function(function) => accumulate(&items[1..], first, &function, ctx),
 	}
-	let first = words[0];
-	let words = &words[1..];
-	let list = if helper::is_list(first) {
-		evaluate_list(&[first], variables)?
-	} else if helper::is_string(first) {
-		evaluate_string(first)?
+}
+
+fn orderable(typ: &VariableT) -> Result<(), CustomErr> {
+	match typ {
+		NumberT | IntT | CharT | BooleanT => Ok(()),
+		ListT(t) => orderable(t),
+		_ => terr!("{} can't be sorted", typ),
+	}
+}
+
+// The order of items of a type that `orderable` accepts, lists are compared item by item
+fn compare(lhs: &Variable, rhs: &Variable) -> Ordering {
+	match (lhs, rhs) {
+		(Number(l), Number(r)) => l.total_cmp(r),
+		(Int(l), Int(r)) => l.cmp(r),
+		(Char(l), Char(r)) => l.cmp(r),
+		(Boolean(l), Boolean(r)) => l.cmp(r),
+		(List(_, l), List(_, r)) => l
+			.iter()
+			.zip(r.iter())
+			.map(|(l, r)| compare(l, r))
+			.find(|order| order.is_ne())
+			.unwrap_or_else(|| l.len().cmp(&r.len())),
+		_ => Ordering::Equal,
+	}
+}
+
+fn sort(list: Variable) -> Result<Variable, CustomErr> {
+	let (typ, mut items) = variable::un_list(list)?;
+	orderable(&typ)?;
+	Rc::make_mut(&mut items).sort_by(compare);
+	Ok(List(typ, items))
+}
+
+// Sorts by the key a function returns for each item, or in the order of a comparison operator
+fn sort_by(
+	list: Variable,
+	callback: Callback,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (typ, mut items) = variable::un_list(list)?;
+	let function = match callback {
+		Callback::Operator(BinaryOp::Lt | BinaryOp::Le) => return sort(List(typ, items)),
+		Callback::Operator(BinaryOp::Gt | BinaryOp::Ge) => {
+			orderable(&typ)?;
+			Rc::make_mut(&mut items).sort_by(|l, r| compare(r, l));
+			return Ok(List(typ, items));
+		}
+		Callback::Operator(op) => {
+			return terr!("`sort_by` needs a function or a comparison, found `{}`", op)
+		}
+		Callback::Function(function) => function,
+	};
+	let keys = each(&items, &function, ctx)?;
+	if let Some(first) = keys.first() {
+		let key_type = variable::to_type(first);
+		orderable(&key_type)?;
+		for key in keys.iter() {
+			variable::assert_type_of(key, &key_type)?;
+		}
+	}
+	let mut order = (0..items.len()).collect::<Vec<_>>();
+	order.sort_by(|&l, &r| compare(&keys[l], &keys[r]));
+	let sorted = order.into_iter().map(|i| items[i].clone()).collect();
+	Ok(List(typ, Rc::new(sorted)))
+}
+
+fn find(
+	list: Variable,
+	function: Rc<Closure>,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (_, items) = variable::un_list(list)?;
+	for item in items.iter() {
+		if variable::un_bool(&ctx.call(&function, vec![item.clone()])?)? {
+			return Ok(item.clone());
+		}
+	}
+	ierr!("no item of the list matched")
+}
+
+// Without a function the items themselves have to be booleans
+fn any_or_all(
+	any: bool,
+	list: Variable,
+	function: Option<Rc<Closure>>,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (typ, items) = variable::un_list(list)?;
+	if function.is_none() {
+		variable::assert_type(&typ, &BooleanT)?;
+	}
+	// The first item that decides the answer stops the search
+	for item in items.iter() {
+		let value = match &function {
+			Some(function) => ctx.call(function, vec![item.clone()])?,
+			None => item.clone(),
+		};
+		if variable::un_bool(&value)? == any {
+			return Ok(Boolean(any));
+		}
+	}
+	Ok(Boolean(!any))
+}
+
+// Pairs of items, which have to be of the same type as a list can only hold one
+fn pair(lhs: Variable, rhs: Variable) -> Variable {
+	List(variable::to_type(&lhs), Rc::new(vec![lhs, rhs]))
+}
+
+fn zip(
+	lhs: Variable,
+	rhs: Variable,
+	callback: Option<Callback>,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (typ_l, lhs) = variable::un_list(lhs)?;
+	let (typ_r, rhs) = variable::un_list(rhs)?;
+	let value = match callback {
+		Some(Callback::Function(function)) => {
+			let results = lhs
+				.iter()
+				.zip(rhs.iter())
+				.map(|(l, r)| ctx.call(&function, vec![l.clone(), r.clone()]))
+				.collect::<Result<Vec<_>, _>>()?;
+			typed_list(results, function.returns.clone(), Builtin::Zip)?
+		}
+		Some(Callback::Operator(op)) => {
+			let results = lhs
+				.iter()
+				.zip(rhs.iter())
+				.map(|(l, r)| variable::binary_op(op, l.clone(), r.clone()))
+				.collect::<Result<Vec<_>, _>>()?;
+			typed_list(results, None, Builtin::Zip)?
+		}
+		None if typ_l == typ_r => {
+			let pairs = lhs
+				.iter()
+				.zip(rhs.iter())
+				.map(|(l, r)| pair(l.clone(), r.clone()))
+				.collect();
+			List(ListT(Box::new(typ_l)), Rc::new(pairs))
+		}
+		None => {
+			return terr!(
+				"can't pair {} with {}, give `zip` a function to combine them",
+				typ_l,
+				typ_r
+			)
+		}
+	};
+	Ok(value)
+}
+
+// The largest item of `list` if `max`, otherwise the smallest
+fn extreme(max: bool, list: Variable) -> Result<Variable, CustomErr> {
+	let (typ, items) = variable::un_list(list)?;
+	orderable(&typ)?;
+	let found = if max {
+		items.iter().max_by(|l, r| compare(l, r))
 	} else {
-		if words.is_empty() {
-			return perr!();
-		}
-		variable::evaluate_statement(&[first], variables)?
-	};
-	variable::assert_list_type_of(&list)?;
-
-	let len = Number(list_len(&list)? as f64);
-	let val = match words {
-		[] => list,
-		["len"] => len,
-		["+", item] => add_to_list(list, len, variable::evaluate_statement(&[item], variables)?)?,
-		["+", index, item] => add_to_list(
-			list,
-			variable::evaluate_statement(&[index], variables)?,
-			variable::evaluate_statement(&[item], variables)?,
-		)?,
-		["-", index] => remove_from_list(list, variable::evaluate_statement(&[index], variables)?)?,
-		["++", rhs] => join_lists(list, variable::evaluate_statement(&[rhs], variables)?)?,
-		["@", index] => get_item(list, variable::evaluate_statement(&[index], variables)?)?,
-		_ => return perr!(),
+		items.iter().min_by(|l, r| compare(l, r))
 	};
-	Ok(val)
+	let name = if max { "max" } else { "min" };
+	found
+		.cloned()
+		.ok_or_else(|| ierrE!("can't take the {} of an empty list", name).into())
+}
+
+fn enumerate(
+	list: Variable,
+	callback: Option<Callback>,
+	ctx: &mut dyn Context,
+) -> Result<Variable, CustomErr> {
+	let (typ, items) = variable::un_list(list)?;
+	let index = |i: usize| Int(i as i64);
+	let value = match callback {
+		Some(Callback::Function(function)) => {
+			let results = items
+				.iter()
+				.enumerate()
+				.map(|(i, item)| ctx.call(&function, vec![index(i), item.clone()]))
+				.collect::<Result<Vec<_>, _>>()?;
+			typed_list(results, function.returns.clone(), Builtin::Enumerate)?
+		}
+		Some(Callback::Operator(op)) => {
+			let results = items
+				.iter()
+				.enumerate()
+				.map(|(i, item)| variable::binary_op(op, index(i), item.clone()))
+				.collect::<Result<Vec<_>, _>>()?;
+			typed_list(results, None, Builtin::Enumerate)?
+		}
+		None if typ == IntT => {
+			let pairs = items
+				.iter()
+				.enumerate()
+				.map(|(i, item)| pair(index(i), item.clone()))
+				.collect();
+			List(ListT(Box::new(IntT)), Rc::new(pairs))
+		}
+		None => {
+			return terr!(
+				"can't pair Int with {}, give `enumerate` a function to combine them",
+				typ
+			)
+		}
+	};
+	Ok(value)
+}
+
+// The most items a range may have, so that one far too long is an error rather than running out of
+// memory
+const MAX_RANGE_LEN: usize = 100_000_000;
+
+/// The numbers from `start` up to `end`, counting by `step` which is 1 if left out. A negative step
+/// counts down. The bounds and step are either all Ints or all Numbers
+pub fn range(
+	start: Variable,
+	end: Variable,
+	inclusive: bool,
+	step: Option<Variable>,
+) -> Result<Variable, CustomErr> {
+	if let Int(start) = start {
+		let step = step.as_ref().map(variable::un_int).transpose()?;
+		return int_range(start, variable::un_int(&end)?, inclusive, step.unwrap_or(1));
+	}
+	let start = variable::un_number(&start)?;
+	let end = variable::un_number(&end)?;
+	let step = match step {
+		Some(step) => variable::un_number(&step)?,
+		None => 1.,
+	};
+	if step == 0. {
+		return serr!("the step of a range can't be 0");
+	}
+	let steps = (end - start) / step;
+	let count = if inclusive {
+		steps.floor() + 1.
+	} else {
+		steps.ceil()
+	};
+	if count == f64::INFINITY {
+		return serr!(
+			"the range from {} to {} never ends",
+			floats::display(start),
+			floats::display(end)
+		);
+	}
+	if count > MAX_RANGE_LEN as f64 {
+		return ierr!(
+			"the range from {} to {} has {} items, more than the {} it may have",
+			floats::display(start),
+			floats::display(end),
+			floats::display(count),
+			MAX_RANGE_LEN
+		);
+	}
+	// Multiplying rather than adding up the steps keeps rounding errors from piling up
+	let items = (0..count.max(0.) as usize)
+		.map(|i| Number(start + i as f64 * step))
+		.collect();
+	Ok(List(NumberT, Rc::new(items)))
+}
+
+fn int_range(start: i64, end: i64, inclusive: bool, step: i64) -> Result<Variable, CustomErr> {
+	if step == 0 {
+		return serr!("the step of a range can't be 0");
+	}
+	// Wide enough that the distance between any two Ints fits
+	let (start, end, step) = (start as i128, end as i128, step as i128);
+	let floor_div = |a: i128, b: i128| {
+		if b > 0 {
+			a.div_euclid(b)
+		} else {
+			(-a).div_euclid(-b)
+		}
+	};
+	let count = if inclusive {
+		floor_div(end - start, step) + 1
+	} else {
+		-floor_div(start - end, step)
+	};
+	if count > MAX_RANGE_LEN as i128 {
+		return ierr!(
+			"the range from {} to {} has {} items, more than the {} it may have",
+			start,
+			end,
+			count,
+			MAX_RANGE_LEN
+		);
+	}
+	let items = (0..count.max(0))
+		.map(|i| Int((start + i * step) as i64))
+		.collect();
+	Ok(List(IntT, Rc::new(items)))
+}
+
+// An index that may count back from the end of a list of length `len`. Bounds of a slice can also
+// be `len` itself
+fn position(value: &Variable, len: usize, what: &str, bound: bool) -> Result<usize, CustomErr> {
+	let n = match value {
+		Int(n) => *n,
+		_ => {
+			return terr!(
+				"expected Int as the {}, found {}",
+				what,
+				variable::to_type(value)
+			)
+		}
+	};
+	let i = if n < 0 {
+		n as i128 + len as i128
+	} else {
+		n as i128
+	};
+	let last = if bound { len as i128 } else { len as i128 - 1 };
+	if i < 0 || i > last {
+		return ierr!("{} {} out of bounds for list of length {}", what, n, len);
+	}
+	Ok(i as usize)
+}
+
+/// The items of `list` from `start` up to `end`, or to the end of the list if there is none, taking
+/// every `step`th one. Negative bounds count from the end of the list
+pub fn slice(
+	list: Variable,
+	start: Variable,
+	end: Option<Variable>,
+	inclusive: bool,
+	step: Option<Variable>,
+) -> Result<Variable, CustomErr> {
+	let (typ, items) = variable::un_list(list)?;
+	let len = items.len();
+	let from = position(&start, len, "start of the slice", true)?;
+	let to = match end {
+		Some(end) => position(&end, len, "end of the slice", true)? + usize::from(inclusive),
+		None => len,
+	};
+	if to > len {
+		return ierr!(
+			"inclusive end of the slice out of bounds for list of length {}",
+			len
+		);
+	}
+	let step = match step {
+		Some(step) => variable::un_int(&step)?,
+		None => 1,
+	};
+	if step < 1 {
+		return serr!("the step of a slice has to be above 0, found {}", step);
+	}
+	let items = items
+		.get(from..to.max(from))
+		.unwrap_or_default()
+		.iter()
+		.step_by(step as usize)
+		.cloned()
+		.collect();
+	Ok(List(typ, Rc::new(items)))
+}
+
+/// Replaces the item at `indices`, one index for each level of nesting, only copying the lists that
+/// are shared with another variable
+pub fn set_item(
+	list: &mut Variable,
+	indices: &[Variable],
+	item: Variable,
+) -> Result<(), CustomErr> {
+	let (index, rest) = match indices.split_first() {
+		Some(split) => split,
+		None => {
+			variable::assert_type_of(&item, &variable::to_type(list))?;
+			*list = item;
+			return Ok(());
+		}
+	};
+	match list {
+		List(_, items) => {
+			let i = position(index, items.len(), "index", false)?;
+			set_item(&mut Rc::make_mut(items)[i], rest, item)
+		}
+		_ => terr!("expected List, found {}", variable::to_type(list)),
+	}
 }
@@ -1,138 +1,206 @@
 use crate::*;
 
-fn create_variable(words: &[&str], variables: &mut Variables) -> Result<Variable, CustomErr> {
-	let res = match &words[1..] {
-		[] => variables.get("last").ok_or(serrE!())?.clone(),
-		["=", ..] => variable::evaluate_statement(&words[2..], variables)?,
-		[typ, "=", ..] => {
-			let rest = &words[3..];
-			match typ.parse::<VariableT>()? {
-				NumberT => floats::evaluate_floats(rest, variables)?,
-				BooleanT => bools::evaluate_bools(rest, variables)?,
-				CharT => chars::char_op(rest, variables)?,
-				ListT(typ) => {
-					let parsed = list::list_op(rest, variables)?;
-					variable::assert_type_of(&parsed, &*typ)?;
-					parsed
-				}
-			}
-		}
-		_ => return perr!(),
-	};
-	let name = variable::owned_name(words.get(0))?;
-	variables.insert(name, res.clone());
-	Ok(res)
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::{error, fmt, mem};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Options {
+	// Abort on the first uncaught error in statements read from files
+	pub strict_files: bool,
+	// Abort on the first uncaught error in statements typed into the REPL
+	pub strict_repl: bool,
+	// How many function calls deep a script may go before it's stopped with a stack overflow
+	pub max_call_depth: usize,
 }
 
-fn create_labels(words: &[&str], labels: &mut Labels, index: usize) -> Result<Variable, CustomErr> {
-	if words.len() != 1 {
-		return perr!();
+impl Default for Options {
+	fn default() -> Self {
+		Options {
+			strict_files: true,
+			strict_repl: false,
+			max_call_depth: 10_000,
+		}
 	}
-	labels.insert(variable::owned_name(words.get(0))?, index);
-	Ok(Boolean(true))
 }
 
-fn create_function(
-	words: &[&str],
-	functions: &mut Functions,
-	index: usize,
-	creating_function: &mut isize,
-) -> Result<Variable, CustomErr> {
-	if words.len() % 2 == 0 {
-		return serr!();
-	}
-	let args = {
-		let mut vec = Vec::with_capacity(words.len() / 2);
-		for (name, typ) in words
-			.iter()
-			.skip(1)
-			.step_by(2)
-			.zip(words.iter().step_by(2).skip(1))
-		{
-			let name = variable::owned_name(Some(name))?;
-			let typ = typ.parse()?;
-			vec.push((name, typ));
+// How many of the innermost calls a stack trace shows
+const STACK_TRACE_FRAMES: usize = 10;
+
+/// How much of the stack to set aside for each function call a script makes
+pub const STACK_PER_CALL: usize = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Loop {
+	// Index of the while statement
+	While(usize),
+	// Index of the for statement, loop variable, next value and end of the range
+	Range(usize, String, Variable, Variable),
+	// Index of the for statement, loop variable, the items and the index of the next one
+	Items(usize, String, Rc<Vec<Variable>>, usize),
+}
+
+// (call stack depth and scope depth when the loop was entered, loop)
+type Loops = Vec<(usize, usize, Loop)>;
+
+impl Loop {
+	// Index of the statement that started the loop
+	fn start(&self) -> usize {
+		match self {
+			Loop::While(start) | Loop::Range(start, ..) | Loop::Items(start, ..) => *start,
 		}
-		vec
-	};
-	let name = variable::owned_name(words.get(0))?;
-	functions.insert(name, (args, index));
-	*creating_function += 1;
-	Ok(Boolean(true))
+	}
 }
 
-fn exit_function(
-	variables: &mut Variables,
-	call_stack: &mut CallStack,
-	jump_next: &mut Option<usize>,
-) -> Result<Variable, CustomErr> {
-	if call_stack.is_empty() {
-		return serr!();
+fn enter_scope(variables: &mut Variables) {
+	if let Some(frame) = variables.last_mut() {
+		frame.scopes.push(HashMap::new());
+	}
+}
+
+// The outermost scope of a call is never left, it holds the arguments and `last`
+fn leave_scope(variables: &mut Variables) {
+	if let Some(frame) = variables.last_mut().filter(|frame| frame.scopes.len() > 1) {
+		frame.scopes.pop();
 	}
-	let return_value = variables.remove("last").ok_or(serrE!())?;
-	let (revert_stack, return_adr) = call_stack.remove(call_stack.len() - 1);
-	*jump_next = Some(return_adr);
-	*variables = revert_stack;
-	Ok(return_value)
 }
 
-fn function_call(
-	words: &[&str],
-	variables: &mut Variables,
-	functions: &Functions,
-	call_stack: &mut CallStack,
-	index: usize,
-	jump_next: &mut Option<usize>,
+fn leave_scopes_to(depth: usize, variables: &mut Variables) {
+	if let Some(frame) = variables.last_mut() {
+		frame.scopes.truncate(depth.max(1));
+	}
+}
+
+fn assigned_value(
+	typ: &Option<VariableT>,
+	value: &Option<Expr>,
+	ctx: &mut dyn Context,
 ) -> Result<Variable, CustomErr> {
-	if words.len() != 2 {
-		return perr!();
-	}
-	if !helper::has_parentheses(words[1]) {
-		return perr!();
-	}
-	let (args_req, pointer) = functions.get(words[0]).ok_or(perrE!())?;
-	let args = helper::split(helper::remove_parentheses(words[1]))?;
-	let mut new_vars = HashMap::new();
-	new_vars.insert("last".to_string(), Boolean(false));
-	for ((name, typ), &arg) in args_req.iter().zip(args.iter()) {
-		let split = helper::split(helper::remove_parentheses(arg))?;
-		let parsed = variable::evaluate_statement(&split, variables)?;
-		variable::assert_type_of(&parsed, typ)?;
-		new_vars.insert(name.clone(), parsed);
-	}
-	call_stack.push((variables.clone(), index));
-	*variables = new_vars;
-	*jump_next = Some(*pointer);
-	Ok(Boolean(false))
+	let res = match value {
+		None => variable::get_variable("last", ctx.variables())?.clone(),
+		Some(expr) => variable::evaluate_expression(expr, ctx)?,
+	};
+	if let Some(typ) = typ {
+		variable::assert_type_of(&res, typ)?;
+	}
+	Ok(res)
+}
+
+// `name + item` or `name ++ other` as the new value of `name`
+fn appended_to<'a>(name: &str, value: &'a Option<Expr>) -> Option<(BinaryOp, &'a Expr, Span)> {
+	match value {
+		Some(Expr {
+			kind: ExprKind::Binary(op @ (BinaryOp::Add | BinaryOp::Join), lhs, rhs),
+			span,
+		}) if lhs.kind == ExprKind::Name(name.to_owned()) => Some((*op, rhs, *span)),
+		_ => None,
+	}
 }
 
-fn if_statement(
-	words: &[&str],
-	variables: &Variables,
-	skipping_if: &mut isize,
+// Changes the variable itself rather than a copy of it, so that the items of a list are only
+// copied if another variable shares them. `lhs` is its value from before `rhs` was evaluated, which
+// is used instead if a function called by `rhs` changed the variable
+fn update_in_place(
+	var: &mut Variable,
+	lhs: Variable,
+	op: BinaryOp,
+	rhs: Variable,
+	typ: &Option<VariableT>,
 ) -> Result<Variable, CustomErr> {
-	let parsed = variable::evaluate_statement(words, variables)?;
-	let b = variable::un_bool(&parsed)?;
-	if !b {
-		*skipping_if += 1;
+	if let Some(typ) = typ {
+		variable::assert_type_of(&lhs, typ)?;
+	}
+	let unchanged = match (&*var, &lhs) {
+		(List(_, items), List(_, before)) => Rc::ptr_eq(items, before),
+		_ => false,
+	};
+	if !unchanged {
+		*var = variable::binary_op(op, lhs, rhs)?;
+		return Ok(var.clone());
 	}
-	Ok(parsed)
+	// So that the items aren't shared with it
+	drop(lhs);
+	match op {
+		BinaryOp::Add => list::push_to_list(var, rhs)?,
+		_ => list::join_into_list(var, rhs)?,
+	}
+	Ok(var.clone())
+}
+
+// `name` if it was declared in the current scope
+fn innermost<'a>(name: &str, variables: &'a mut Variables) -> Option<&'a mut Variable> {
+	variables
+		.last_mut()
+		.and_then(|frame| frame.scopes.last_mut())
+		.and_then(|scope| scope.get_mut(name))
+}
+
+fn evaluate_indices(indices: &[Expr], ctx: &mut dyn Context) -> Result<Vec<Variable>, CustomErr> {
+	indices
+		.iter()
+		.map(|index| variable::evaluate_expression(index, ctx))
+		.collect()
+}
+
+// The variable `set` assigns to
+fn assignment_target<'a>(
+	name: &str,
+	variables: &'a mut Variables,
+	call_stack: &CallStack,
+) -> Result<&'a mut Variable, CustomErr> {
+	let declared = call_stack
+		.last()
+		.map(|call| call.globals.contains(name))
+		.unwrap_or(false);
+	let (top_level, calls) = variables.split_first_mut().ok_or(serrE!())?;
+	let scopes = match calls.last_mut() {
+		None => top_level.scopes.as_mut_slice(),
+		Some(_) if declared => &mut top_level.scopes[..1],
+		Some(frame) => {
+			let is_local = frame.scopes.iter().any(|scope| scope.contains_key(name));
+			if !is_local {
+				// The function's own copy, which its next call doesn't see
+				if let Some(value) = frame.captured.get(name) {
+					let value = value.clone();
+					return Ok(frame.scopes[0].entry(name.to_owned()).or_insert(value));
+				}
+			}
+			let is_global = top_level
+				.scopes
+				.iter()
+				.take(1)
+				.any(|scope| scope.contains_key(name));
+			if !is_local && is_global {
+				return nerr!(
+					"`{}` is a global variable, declare it with `global {}` to assign to it",
+					name,
+					name
+				);
+			}
+			frame.scopes.as_mut_slice()
+		}
+	};
+	variable::get_variable_mut(name, scopes)
 }
 
-fn print(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
+fn print(exprs: &[Expr], ctx: &mut dyn Context) -> Result<Variable, CustomErr> {
+	// Everything is evaluated first, so that what the functions it calls print comes before the line
+	let results = exprs
+		.iter()
+		.map(|expr| variable::evaluate_expression(expr, ctx))
+		.collect::<Result<Vec<_>, _>>()?;
 	let stdout = io::stdout();
 	let mut lock = stdout.lock();
 	write!(lock, "> ")?;
-	for &word in words {
-		let result = variables.get(word).ok_or(perrE!())?;
+	for result in results {
 		write!(lock, "{} ", result)?;
 	}
 	writeln!(lock)?;
 	Ok(Boolean(true))
 }
 
-fn print_string(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	let (typ, vec) = variable::un_list(variable::evaluate_statement(words, variables)?)?;
+fn print_string(expr: &Expr, ctx: &mut dyn Context) -> Result<Variable, CustomErr> {
+	let (typ, vec) = variable::un_list(variable::evaluate_expression(expr, ctx)?)?;
 	variable::assert_type(&typ, &CharT)?;
 	let stdout = io::stdout();
 	let mut lock = stdout.lock();
@@ -143,8 +211,8 @@ fn print_string(words: &[&str], variables: &Variables) -> Result<Variable, Custo
 	Ok(Boolean(true))
 }
 
-fn print_type(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	let var = variable::evaluate_statement(words, variables)?;
+fn print_type(expr: &Expr, ctx: &mut dyn Context) -> Result<Variable, CustomErr> {
+	let var = variable::evaluate_expression(expr, ctx)?;
 	println!("> {}", variable::to_type(&var));
 	Ok(var)
 }
@@ -153,124 +221,1034 @@ fn clear() -> Result<Variable, CustomErr> {
 	unimplemented!()
 }
 
-fn jump(
-	words: &[&str],
-	labels: &Labels,
-	jump_next: &mut Option<usize>,
-) -> Result<Variable, CustomErr> {
-	let &word = words.get(0).ok_or(perrE!())?;
-	let &target = labels.get(word).ok_or(perrE!())?;
-	*jump_next = Some(target);
-	Ok(Boolean(true))
+fn report(code: &Code, index: usize, error: &CustomErr) {
+	eprintln!("error: {}", error);
+	if let Some(location) = code.location(index, errors::span_of(error)) {
+		eprintln!("{}", location);
+	}
 }
 
-fn jump_rel(
-	words: &[&str],
-	variables: &Variables,
-	index: usize,
-	jump_next: &mut Option<usize>,
-) -> Result<Variable, CustomErr> {
-	let n = variable::un_number(&variable::evaluate_statement(words, variables)?)?;
-	*jump_next = Some((index as isize).saturating_add(n as isize) as usize);
-	Ok(Number(n))
+fn print_stack_trace(code: &Code, call_stack: &CallStack) {
+	if call_stack.is_empty() {
+		return;
+	}
+	eprintln!("stack trace:");
+	for call in call_stack.iter().rev().take(STACK_TRACE_FRAMES) {
+		match code.location(call.call_site, None) {
+			Some(l) => eprintln!(
+				"  in `{}`, called from {}:{}:{}",
+				call.name, l.path, l.line, l.column
+			),
+			None => eprintln!("  in `{}`", call.name),
+		}
+	}
+	if call_stack.len() > STACK_TRACE_FRAMES {
+		eprintln!("  ... and {} more", call_stack.len() - STACK_TRACE_FRAMES);
+	}
 }
 
-fn solve_function_or_variable(
-	words: &[&str],
-	variables: &mut Variables,
-	functions: &Functions,
-	call_stack: &mut CallStack,
-	index: usize,
-	jump_next: &mut Option<usize>,
-) -> Result<Variable, CustomErr> {
-	function_call(words, variables, functions, call_stack, index, jump_next)
-		.or_else(|_| variable::evaluate_statement(words, variables))
+/// Stops the script from however deep in calls it is: `exit` without an error, otherwise an
+/// uncaught error in strict mode, which has already been reported
+#[derive(Debug)]
+struct Halt(Option<CustomErr>);
+
+impl error::Error for Halt {}
+impl fmt::Display for Halt {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match &self.0 {
+			Some(error) => write!(f, "{}", error),
+			None => write!(f, "exit"),
+		}
+	}
 }
 
-pub fn run(mut code: Code) -> Result<(), CustomErr> {
-	eprint!(
-		"Source at:\thttps://github.com/SKyletoft/lang_experiment\nCompiled at:\t{}",
-		include_str!("../target/date.txt")
-	);
-	let mut variables: Variables = HashMap::new();
-	let mut labels: Labels = HashMap::new();
-	let mut functions: Functions = HashMap::new();
-	let mut call_stack: CallStack = Vec::new();
-	let mut jump_next: Option<usize> = None;
-	let mut creating_function: isize = 0;
-	let mut skipping_if: isize = 0;
-
-	variables.insert("last".to_owned(), Boolean(false));
-
-	loop {
-		let index = code.index.wrapping_add(1);
-		let (input_line, interactive) = code.next_line()?;
-		let words = {
-			let words = helper::split(input_line);
-			if words.is_err() {
-				eprintln!("{:3}: {:?}", index, words);
-				continue;
+/// Everything a script has set up while running
+struct Interpreter {
+	code: Code,
+	options: Options,
+	variables: Variables,
+	labels: Labels,
+	call_stack: CallStack,
+	// The statement run next is the one after this
+	jump_next: Option<usize>,
+	creating_function: isize,
+	skipping_if: isize,
+	// Whether the if being skipped is still looking for an elif or else to run
+	seeking_branch: bool,
+	handlers: Handlers,
+	// The caught error while looking for its catch, and how many try blocks deep that search is
+	catching: Option<Variable>,
+	nested_try: isize,
+	skipping_catch: isize,
+	loops: Loops,
+	skipping_loop: isize,
+	// Set by continue to run the end of the loop once it has been skipped to
+	continuing: bool,
+}
+
+impl Context for Interpreter {
+	fn variables(&self) -> &Variables {
+		&self.variables
+	}
+
+	// Runs the body from where the function was defined, and then goes back to the statement that
+	// called it
+	fn call(&mut self, function: &Rc<Closure>, args: Vec<Variable>) -> Result<Variable, CustomErr> {
+		let call_site = self.code.index;
+		self.function_call(function, args, call_site)?;
+		let calls = self.call_stack.len();
+		// The calling if may be part way through choosing its branch
+		let seeking_branch = mem::take(&mut self.seeking_branch);
+		self.code.index = function.body;
+		let result = self.execute(calls);
+		// Whatever the call left behind if it failed
+		self.call_stack.truncate(calls - 1);
+		self.variables.truncate(calls);
+		self.handlers.retain(|&(_, depth, _, _)| depth < calls);
+		self.loops.retain(|&(depth, _, _)| depth < calls);
+		self.code.index = call_site;
+		self.seeking_branch = seeking_branch;
+		result
+	}
+}
+
+impl Interpreter {
+	fn new(code: Code, options: Options) -> Self {
+		Interpreter {
+			code,
+			options,
+			variables: vec![Frame::new(Rc::default(), HashMap::new())],
+			labels: HashMap::new(),
+			call_stack: Vec::new(),
+			jump_next: None,
+			creating_function: 0,
+			skipping_if: 0,
+			seeking_branch: false,
+			handlers: Vec::new(),
+			catching: None,
+			nested_try: 0,
+			skipping_catch: 0,
+			loops: Vec::new(),
+			skipping_loop: 0,
+			continuing: false,
+		}
+	}
+
+	fn create_variable(
+		&mut self,
+		name: &str,
+		indices: &[Expr],
+		typ: &Option<VariableT>,
+		value: &Option<Expr>,
+	) -> Result<Variable, CustomErr> {
+		if !indices.is_empty() {
+			let indices = evaluate_indices(indices, self)?;
+			let res = assigned_value(typ, value, self)?;
+			let variables = &mut self.variables;
+			// `last` may share the list and is about to be replaced anyway
+			variable::set_last(Boolean(false), variables)?;
+			match innermost(name, variables) {
+				Some(var) => list::set_item(var, &indices, res.clone())?,
+				None => {
+					let mut var = variable::get_variable(name, variables)?.clone();
+					list::set_item(&mut var, &indices, res.clone())?;
+					variable::declare(name, var, variables)?;
+				}
 			}
-			words.expect("This can't happen")
+			return Ok(res);
+		}
+		let in_place = appended_to(name, value)
+			.and_then(|append| Some((append, innermost(name, &mut self.variables)?.clone())));
+		if let Some(((op, rhs, span), lhs)) = in_place {
+			let rhs = variable::evaluate_expression(rhs, self)?;
+			// `last` may share the list and is about to be replaced anyway
+			variable::set_last(Boolean(false), &mut self.variables)?;
+			let var = innermost(name, &mut self.variables).ok_or(serrE!())?;
+			return update_in_place(var, lhs, op, rhs, typ).map_err(|e| errors::at(e, span));
+		}
+		let res = assigned_value(typ, value, self)?;
+		variable::declare(name, res.clone(), &mut self.variables)?;
+		Ok(res)
+	}
+
+	fn set_variable(
+		&mut self,
+		name: &str,
+		indices: &[Expr],
+		typ: &Option<VariableT>,
+		value: &Option<Expr>,
+	) -> Result<Variable, CustomErr> {
+		if !indices.is_empty() {
+			let indices = evaluate_indices(indices, self)?;
+			let res = assigned_value(typ, value, self)?;
+			variable::set_last(Boolean(false), &mut self.variables)?;
+			let var = assignment_target(name, &mut self.variables, &self.call_stack)?;
+			list::set_item(var, &indices, res.clone())?;
+			return Ok(res);
+		}
+		if let Some((op, rhs, span)) = appended_to(name, value) {
+			let lhs = assignment_target(name, &mut self.variables, &self.call_stack)?.clone();
+			let rhs = variable::evaluate_expression(rhs, self)?;
+			// `last` may share the list and is about to be replaced anyway
+			variable::set_last(Boolean(false), &mut self.variables)?;
+			let var = assignment_target(name, &mut self.variables, &self.call_stack)?;
+			return update_in_place(var, lhs, op, rhs, typ).map_err(|e| errors::at(e, span));
+		}
+		let res = assigned_value(typ, value, self)?;
+		*assignment_target(name, &mut self.variables, &self.call_stack)? = res.clone();
+		Ok(res)
+	}
+
+	fn declare_globals(&mut self, names: &[String]) -> Result<Variable, CustomErr> {
+		// Everything at the top level already is global
+		let top_level = self
+			.variables
+			.first()
+			.and_then(|frame| frame.scopes.first());
+		if let Some(call) = self.call_stack.last_mut() {
+			for name in names {
+				if !top_level
+					.map(|scope| scope.contains_key(name))
+					.unwrap_or(false)
+				{
+					return nerr!("unknown global variable `{}`", name);
+				}
+				call.globals.insert(name.clone());
+			}
+		}
+		Ok(Boolean(true))
+	}
+
+	fn create_labels(&mut self, name: &str, index: usize) -> Result<Variable, CustomErr> {
+		let depth = variable::scope_depth(&self.variables);
+		self.labels.insert(name.to_owned(), (index, depth));
+		Ok(Boolean(true))
+	}
+
+	// Binds the function in the current scope like any other variable
+	fn create_function(
+		&mut self,
+		name: &str,
+		args: &ast::Params,
+		returns: &Option<VariableT>,
+		index: usize,
+	) -> Result<Variable, CustomErr> {
+		let params = variable::parameters(args, self)?;
+		let function = Closure {
+			name: name.to_owned(),
+			params,
+			returns: returns.clone(),
+			body: index,
+			captured: variable::captured(&self.variables),
 		};
+		variable::declare(name, Function(Rc::new(function)), &mut self.variables)?;
+		Ok(Boolean(true))
+	}
 
-		if words.is_empty() {
-			continue;
+	// Leaves the function with the value of `value`, or `last` if there is none
+	fn exit_function(&mut self, value: &Option<Expr>) -> Result<Variable, CustomErr> {
+		if self.call_stack.is_empty() {
+			return serr!("return outside of a function");
 		}
-		if creating_function >= 1 {
-			if words[0] == "end" {
-				creating_function -= 1;
+		let return_value = match value {
+			Some(expr) => variable::evaluate_expression(expr, self)?,
+			None => variable::get_variable("last", &self.variables)?.clone(),
+		};
+		let call = self.call_stack.last().ok_or(serrE!())?;
+		if let Some(returns) = &call.returns {
+			let found = variable::to_type(&return_value);
+			if found != *returns {
+				return terr!("`{}` should return {}, found {}", call.name, returns, found);
 			}
-			continue;
 		}
-		if skipping_if >= 1 {
-			match words[0] {
-				"endif" => skipping_if -= 1,
-				"if" => skipping_if += 1,
-				_ => {}
+		self.call_stack.pop();
+		self.variables.pop();
+		Ok(return_value)
+	}
+
+	// Enters a call of `function` from the statement at `index`
+	fn function_call(
+		&mut self,
+		function: &Rc<Closure>,
+		args: Vec<Variable>,
+		index: usize,
+	) -> Result<(), CustomErr> {
+		let Closure {
+			name,
+			params,
+			returns,
+			..
+		} = &**function;
+		let max_call_depth = self.options.max_call_depth;
+		if self.call_stack.len() >= max_call_depth {
+			return Err(Box::new(code_error!(
+				StackOverflow;
+				"calling `{}` would exceed the maximum call depth of {}",
+				name,
+				max_call_depth
+			)));
+		}
+		let required = params.iter().filter(|(_, _, d)| d.is_none()).count();
+		if args.len() < required || args.len() > params.len() {
+			let expected = if required == params.len() {
+				required.to_string()
+			} else {
+				format!("{} to {}", required, params.len())
+			};
+			return terr!(
+				"wrong number of arguments to `{}`: expected {}, found {}",
+				variable::signature(name, params, returns),
+				expected,
+				args.len()
+			);
+		}
+		let mut new_vars = HashMap::new();
+		// A named function can call itself even if it isn't visible from where it was defined
+		if variable::is_ok(name) {
+			new_vars.insert(name.clone(), Function(function.clone()));
+		}
+		new_vars.insert("last".to_string(), Boolean(false));
+		let mut args = args.into_iter();
+		for (arg_name, typ, default) in params {
+			let arg = match args.next() {
+				Some(arg) => arg,
+				None => default.clone().ok_or(serrE!())?,
+			};
+			let found = variable::to_type(&arg);
+			if found != *typ {
+				return terr!(
+					"argument `{}` of `{}`: expected {}, found {}",
+					arg_name,
+					variable::signature(name, params, returns),
+					typ,
+					found
+				);
 			}
-			continue;
+			new_vars.insert(arg_name.clone(), arg);
+		}
+		self.call_stack.push(Call {
+			call_site: index,
+			name: name.clone(),
+			returns: returns.clone(),
+			globals: HashSet::new(),
+		});
+		self.variables
+			.push(Frame::new(function.captured.clone(), new_vars));
+		Ok(())
+	}
+
+	fn if_statement(&mut self, condition: &Expr) -> Result<Variable, CustomErr> {
+		let parsed = variable::evaluate_expression(condition, self)?;
+		let b = variable::un_bool(&parsed)?;
+		if b {
+			enter_scope(&mut self.variables);
+		} else {
+			self.skipping_if += 1;
+			self.seeking_branch = true;
+		}
+		Ok(parsed)
+	}
+
+	fn elif_statement(&mut self, condition: &Expr) -> Result<Variable, CustomErr> {
+		if !self.seeking_branch {
+			// An earlier branch was taken
+			leave_scope(&mut self.variables);
+			self.skipping_if += 1;
+			return Ok(Boolean(false));
 		}
+		let parsed = self.if_statement(condition)?;
+		if parsed == Boolean(true) {
+			self.seeking_branch = false;
+		}
+		Ok(parsed)
+	}
+
+	// None of the branches of an if are run once one of its conditions has failed
+	fn abandon_if(&mut self, statement: &Statement) {
+		if matches!(statement, Statement::If(_) | Statement::Elif(_)) {
+			self.skipping_if += 1;
+			self.seeking_branch = false;
+		}
+	}
+
+	fn else_statement(&mut self) -> Result<Variable, CustomErr> {
+		// Only reached by running the end of an earlier branch
+		leave_scope(&mut self.variables);
+		self.skipping_if += 1;
+		Ok(Boolean(true))
+	}
 
-		let rest = &words[1..];
-		let result = match words[0] {
-			"exit" => {
-				return Ok(());
+	fn end_if(&mut self) -> Result<Variable, CustomErr> {
+		leave_scope(&mut self.variables);
+		Ok(Boolean(true))
+	}
+
+	// The innermost loop, unless it belongs to a calling function
+	fn current_loop(&mut self) -> Option<&mut Loop> {
+		let calls = self.call_stack.len();
+		self.loops
+			.last_mut()
+			.filter(|(depth, _, _)| *depth == calls)
+			.map(|(_, _, l)| l)
+	}
+
+	// Enters the body of `l` in a scope of its own
+	fn enter_loop(&mut self, l: Loop) {
+		let depth = variable::scope_depth(&self.variables);
+		self.loops.push((self.call_stack.len(), depth, l));
+		enter_scope(&mut self.variables);
+	}
+
+	// Leaves the scopes of the innermost loop, as well as of any blocks inside it
+	fn leave_loop(&mut self) {
+		if let Some((_, depth, _)) = self.loops.pop() {
+			leave_scopes_to(depth, &mut self.variables);
+		}
+	}
+
+	fn while_loop(&mut self, condition: &Expr, index: usize) -> Result<Variable, CustomErr> {
+		let parsed = variable::evaluate_expression(condition, self)?;
+		if variable::un_bool(&parsed)? {
+			self.enter_loop(Loop::While(index));
+		} else {
+			self.skipping_loop += 1;
+		}
+		Ok(parsed)
+	}
+
+	fn end_while(&mut self) -> Result<Variable, CustomErr> {
+		if let Some(&mut Loop::While(start)) = self.current_loop() {
+			self.leave_loop();
+			// Land on the while statement itself so the condition is checked again
+			self.jump_next = Some(start.wrapping_sub(1));
+			Ok(Boolean(true))
+		} else {
+			serr!("endwhile without a matching while")
+		}
+	}
+
+	fn for_range(
+		&mut self,
+		name: &str,
+		start: &Expr,
+		end: &Expr,
+		index: usize,
+	) -> Result<Variable, CustomErr> {
+		let start = variable::evaluate_expression(start, self)?;
+		let end = variable::evaluate_expression(end, self)?;
+		let entered = variable::un_bool(&variable::binary_op(
+			BinaryOp::Lt,
+			start.clone(),
+			end.clone(),
+		)?)?;
+		if entered {
+			let next = floats::successor(&start)?;
+			self.enter_loop(Loop::Range(index, name.to_owned(), next, end));
+			variable::declare(name, start, &mut self.variables)?;
+		} else {
+			self.skipping_loop += 1;
+		}
+		Ok(Boolean(entered))
+	}
+
+	fn for_each(&mut self, name: &str, list: &Expr, index: usize) -> Result<Variable, CustomErr> {
+		let list = variable::evaluate_expression(list, self)?;
+		let (_, items) = variable::un_list(list)?;
+		let first = items.first().cloned();
+		let entered = first.is_some();
+		if let Some(first) = first {
+			self.enter_loop(Loop::Items(index, name.to_owned(), items, 1));
+			variable::declare(name, first, &mut self.variables)?;
+		} else {
+			self.skipping_loop += 1;
+		}
+		Ok(Boolean(entered))
+	}
+
+	fn end_for(&mut self) -> Result<Variable, CustomErr> {
+		let (start, name, next) = match self.current_loop() {
+			Some(Loop::Range(start, name, next, end)) => {
+				let more = variable::binary_op(BinaryOp::Lt, next.clone(), end.clone())?;
+				let value = if variable::un_bool(&more)? {
+					let value = next.clone();
+					*next = floats::successor(next)?;
+					Some(value)
+				} else {
+					None
+				};
+				(*start, name.clone(), value)
 			}
-			"let" => create_variable(rest, &mut variables),
-			"if" => if_statement(rest, &variables, &mut skipping_if),
-			"endif" => Ok(Boolean(true)),
-			"print" => print(rest, &variables),
-			"clear" => clear(),
-			"label" => create_labels(rest, &mut labels, index),
-			"jump" => jump(rest, &labels, &mut jump_next),
-			"jump_rel" => jump_rel(rest, &variables, index, &mut jump_next),
-			"type" => print_type(rest, &variables),
-			"end" => exit_function(&mut variables, &mut call_stack, &mut jump_next),
-			"return" => exit_function(&mut variables, &mut call_stack, &mut jump_next),
-			"fn" => create_function(rest, &mut functions, index, &mut creating_function),
-			"puts" => print_string(rest, &variables),
-			_ => solve_function_or_variable(
-				&words,
-				&mut variables,
-				&functions,
-				&mut call_stack,
-				index,
-				&mut jump_next,
-			),
-		};
-		if let Ok(last) = result {
-			if interactive && creating_function == 0 && call_stack.is_empty() {
-				println!("> {}", &last);
+			Some(Loop::Items(start, name, items, next)) => {
+				let value = items.get(*next).cloned();
+				*next += 1;
+				(*start, name.clone(), value)
 			}
-			*variables.get_mut("last").ok_or(serrE!())? = last;
+			_ => return serr!("endfor without a matching for"),
+		};
+		if let Some(value) = next {
+			// Every iteration starts with a fresh scope
+			let (_, depth, _) = self.loops.last().ok_or(serrE!())?;
+			leave_scopes_to(*depth, &mut self.variables);
+			enter_scope(&mut self.variables);
+			variable::declare(&name, value, &mut self.variables)?;
+			// Land on the for statement so the body starts over
+			self.jump_next = Some(start);
+		} else {
+			self.leave_loop();
+		}
+		Ok(Boolean(true))
+	}
+
+	fn break_loop(&mut self) -> Result<Variable, CustomErr> {
+		if self.current_loop().is_none() {
+			return serr!("break outside of a loop");
+		}
+		self.leave_loop();
+		// Including the try blocks inside it
+		let loop_count = self.loops.len();
+		self.handlers
+			.retain(|&(_, _, loop_depth, _)| loop_depth <= loop_count);
+		self.skipping_loop += 1;
+		Ok(Boolean(true))
+	}
+
+	fn continue_loop(&mut self) -> Result<Variable, CustomErr> {
+		if self.current_loop().is_none() {
+			return serr!("continue outside of a loop");
+		}
+		self.skipping_loop += 1;
+		self.continuing = true;
+		Ok(Boolean(true))
+	}
+
+	fn jump(&mut self, name: &str) -> Result<Variable, CustomErr> {
+		let &(target, depth) = self
+			.labels
+			.get(name)
+			.ok_or_else(|| nerrE!("unknown label `{}`", name))?;
+		// Jumping out of a block leaves its scope
+		if depth < variable::scope_depth(&self.variables) {
+			leave_scopes_to(depth, &mut self.variables);
+		}
+		// As well as any try blocks and loops of this call that don't enclose the label
+		let calls = self.call_stack.len();
+		self.handlers
+			.retain(|&(try_index, try_calls, _, try_depth)| {
+				try_calls < calls || (try_index < target && try_depth < depth)
+			});
+		self.loops.retain(|(loop_calls, loop_depth, l)| {
+			*loop_calls < calls || (l.start() < target && *loop_depth < depth)
+		});
+		self.jump_next = Some(target);
+		Ok(Boolean(true))
+	}
+
+	fn jump_rel(
+		&mut self,
+		offset: &Expr,
+		index: usize,
+		line_count: usize,
+	) -> Result<Variable, CustomErr> {
+		let offset = variable::evaluate_expression(offset, self)?;
+		let n = variable::un_int(&offset)?;
+		// Counted from the next statement. The line after the last one is the next one read from
+		// the REPL
+		let target = index as i128 + 1 + n as i128;
+		if target < 0 {
+			return ierr!("`jump_rel {}` goes before the start of the code", n);
+		}
+		if target > line_count as i128 {
+			return ierr!("`jump_rel {}` goes past the end of the code", n);
+		}
+		// The statement run next is the one after `jump_next`
+		self.jump_next = Some((target as usize).wrapping_sub(1));
+		Ok(Int(n))
+	}
+
+	// Whether statements are passed over looking for a catch or the end of a block
+	fn is_skipping(&self) -> bool {
+		self.catching.is_some()
+			|| self.skipping_catch >= 1
+			|| self.skipping_loop >= 1
+			|| self.creating_function >= 1
+			|| self.skipping_if >= 1
+	}
+
+	fn is_strict(&self, index: usize) -> bool {
+		if self.code.is_from_stdin(index) {
+			self.options.strict_repl
 		} else {
-			eprintln!("{:3}: {:?}", index, result);
-			*variables.get_mut("last").ok_or(serrE!())? = Boolean(false);
+			self.options.strict_files
+		}
+	}
+
+	fn try_statement(&mut self, index: usize) -> Result<Variable, CustomErr> {
+		let depth = variable::scope_depth(&self.variables);
+		self.handlers
+			.push((index, self.call_stack.len(), self.loops.len(), depth));
+		enter_scope(&mut self.variables);
+		Ok(Boolean(true))
+	}
+
+	fn catch_statement(&mut self) -> Result<Variable, CustomErr> {
+		let (_, _, _, depth) = self
+			.handlers
+			.pop()
+			.ok_or(serrE!("catch without a matching try"))?;
+		leave_scopes_to(depth, &mut self.variables);
+		self.skipping_catch += 1;
+		Ok(Boolean(true))
+	}
+
+	fn end_try(&mut self) -> Result<Variable, CustomErr> {
+		// Only reached by running the catch block
+		leave_scope(&mut self.variables);
+		Ok(Boolean(true))
+	}
+
+	/// Unwinds to the innermost enclosing try block if it's in the call `calls` deep. If it's in a
+	/// calling function the error is returned, to unwind the calls in between. Without one the error
+	/// is reported and, in strict mode, stops the script
+	fn handle_error(
+		&mut self,
+		error: CustomErr,
+		index: usize,
+		calls: usize,
+	) -> Result<(), CustomErr> {
+		if error.is::<Halt>() {
+			return Err(error);
+		}
+		if let (Some(value), Some(&(try_index, depth, loop_depth, scope_depth))) =
+			(variable::from_error(&error), self.handlers.last())
+		{
+			if depth < calls {
+				return Err(error);
+			}
+			self.handlers.pop();
+			self.loops.truncate(loop_depth);
+			leave_scopes_to(scope_depth, &mut self.variables);
+			// The ifs being skipped were inside the try block
+			self.skipping_if = 0;
+			self.seeking_branch = false;
+			self.code.index = try_index;
+			self.catching = Some(value);
+			return Ok(());
 		}
-		if let Some(target) = jump_next {
-			code.index = target;
-			jump_next = None;
+		report(&self.code, index, &error);
+		if self.is_strict(index) {
+			print_stack_trace(&self.code, &self.call_stack);
+			return Err(Box::new(Halt(Some(error))));
 		}
+		let overflowed = error
+			.downcast_ref::<CodeError>()
+			.map(|e| e.kind == ErrorKind::StackOverflow)
+			.unwrap_or(false);
+		if overflowed {
+			print_stack_trace(&self.code, &self.call_stack);
+		}
+		variable::set_last(Boolean(false), &mut self.variables)?;
+		Ok(())
+	}
+
+	// Runs statements until the call `calls` deep returns, giving back what it returned. The top
+	// level only stops with a `Halt`
+	fn execute(&mut self, calls: usize) -> Result<Variable, CustomErr> {
+		loop {
+			let index = self.code.index.wrapping_add(1);
+			let (statement, interactive, line_count) = self.code.next_line()?;
+			let statement = match statement {
+				Ok(statement) => statement,
+				// A statement that is being skipped over doesn't run, so it doesn't have to parse
+				Err(_) if self.is_skipping() => continue,
+				Err(e) => {
+					self.handle_error(e, index, calls)?;
+					continue;
+				}
+			};
+
+			if let Some(error) = self.catching.take() {
+				match &*statement {
+					Statement::Catch(name) if self.nested_try == 0 => {
+						enter_scope(&mut self.variables);
+						variable::declare(name, error, &mut self.variables)?;
+						continue;
+					}
+					Statement::Try => self.nested_try += 1,
+					Statement::EndTry => self.nested_try -= 1,
+					_ => {}
+				}
+				self.catching = Some(error);
+				continue;
+			}
+			if self.skipping_catch >= 1 {
+				match &*statement {
+					Statement::EndTry => self.skipping_catch -= 1,
+					Statement::Try => self.skipping_catch += 1,
+					_ => {}
+				}
+				continue;
+			}
+			if self.skipping_loop >= 1 {
+				match &*statement {
+					Statement::While(_) | Statement::ForRange(..) | Statement::ForEach(..) => {
+						self.skipping_loop += 1
+					}
+					Statement::EndWhile | Statement::EndFor => self.skipping_loop -= 1,
+					_ => {}
+				}
+				if self.skipping_loop >= 1 || !self.continuing {
+					continue;
+				}
+				self.continuing = false;
+				// The try blocks that continue skipped the end of were inside this loop
+				let loop_count = self.loops.len();
+				self.handlers
+					.retain(|&(_, _, loop_depth, _)| loop_depth < loop_count);
+			}
+			if self.creating_function >= 1 {
+				if let Statement::End = *statement {
+					self.creating_function -= 1;
+				} else if statement.opens_function() {
+					self.creating_function += 1;
+				}
+				continue;
+			}
+			if self.skipping_if >= 1 {
+				match &*statement {
+					Statement::EndIf => {
+						self.skipping_if -= 1;
+						if self.skipping_if == 0 {
+							self.seeking_branch = false;
+						}
+					}
+					Statement::If(_) => self.skipping_if += 1,
+					Statement::Else if self.skipping_if == 1 && self.seeking_branch => {
+						self.skipping_if = 0;
+						self.seeking_branch = false;
+						enter_scope(&mut self.variables);
+					}
+					Statement::Elif(_) if self.skipping_if == 1 && self.seeking_branch => {
+						self.skipping_if = 0
+					}
+					_ => {}
+				}
+				// An elif that might be taken still has to check its condition
+				let candidate = self.seeking_branch && matches!(*statement, Statement::Elif(_));
+				if self.skipping_if >= 1 || !candidate {
+					continue;
+				}
+			}
+
+			let returning = matches!(*statement, Statement::End | Statement::Return(_));
+			let opens_function = statement.opens_function();
+			let result = match &*statement {
+				Statement::Exit => {
+					return Err(Box::new(Halt(None)));
+				}
+				Statement::Let(name, indices, typ, value) => {
+					self.create_variable(name, indices, typ, value)
+				}
+				Statement::Set(name, indices, typ, value) => {
+					self.set_variable(name, indices, typ, value)
+				}
+				Statement::Global(names) => self.declare_globals(names),
+				Statement::If(condition) => self.if_statement(condition),
+				Statement::Elif(condition) => self.elif_statement(condition),
+				Statement::Else => self.else_statement(),
+				Statement::EndIf => self.end_if(),
+				Statement::Print(exprs) => print(exprs, self),
+				Statement::Clear => clear(),
+				Statement::Label(name) => self.create_labels(name, index),
+				Statement::Jump(name) => self.jump(name),
+				Statement::JumpRel(offset) => self.jump_rel(offset, index, line_count),
+				Statement::Type(expr) => print_type(expr, self),
+				Statement::End => self.exit_function(&None),
+				Statement::Return(value) => self.exit_function(value),
+				Statement::Function(name, args, returns) => {
+					self.create_function(name, args, returns, index)
+				}
+				Statement::Puts(expr) => print_string(expr, self),
+				Statement::Expr(expr) => variable::evaluate_expression(expr, self),
+				Statement::Try => self.try_statement(index),
+				Statement::Catch(_) => self.catch_statement(),
+				Statement::EndTry => self.end_try(),
+				Statement::While(condition) => self.while_loop(condition, index),
+				Statement::EndWhile => self.end_while(),
+				Statement::ForRange(name, start, end) => self.for_range(name, start, end, index),
+				Statement::ForEach(name, list) => self.for_each(name, list, index),
+				Statement::EndFor => self.end_for(),
+				Statement::Break => self.break_loop(),
+				Statement::Continue => self.continue_loop(),
+			};
+			match result {
+				// The function has returned, and its frame is gone
+				Ok(value) if returning => return Ok(value),
+				Ok(last) => {
+					if interactive && self.creating_function == 0 && self.call_stack.is_empty() {
+						println!("> {}", &last);
+					}
+					variable::set_last(last, &mut self.variables)?;
+				}
+				Err(e) => {
+					self.jump_next = None;
+					self.abandon_if(&statement);
+					self.handle_error(e, index, calls)?;
+				}
+			}
+			// Skip the body of a function defined by the statement, unless its error was caught
+			if opens_function && self.catching.is_none() {
+				self.creating_function += 1;
+			}
+			if let Some(target) = self.jump_next.take() {
+				self.code.index = target;
+			}
+		}
+	}
+
+	fn run(&mut self) -> Result<(), CustomErr> {
+		variable::set_last(Boolean(false), &mut self.variables)?;
+		let halt = match self.execute(0) {
+			Ok(_) => return Ok(()),
+			Err(e) => e.downcast::<Halt>()?,
+		};
+		halt.0.map_or(Ok(()), Err)
+	}
+}
+
+/// Runs until `exit`. Uncaught errors in strict mode are reported along with a stack trace
+/// and then returned
+pub fn run(code: Code, options: Options) -> Result<(), CustomErr> {
+	eprint!(
+		"Source at:\thttps://github.com/SKyletoft/lang_experiment\nCompiled at:\t{}",
+		include_str!("../target/date.txt")
+	);
+	Interpreter::new(code, options).run()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Runs `script` until it exits, failing on any uncaught error
+	fn run_script(script: &str) -> Interpreter {
+		let mut interpreter = Interpreter::new(Code::new(), Options::default());
+		run_more(&mut interpreter, script);
+		interpreter
+	}
+
+	// Runs `script` after what the interpreter has already run
+	fn run_more(interpreter: &mut Interpreter, script: &str) {
+		interpreter
+			.code
+			.add_source("<test>", format!("{}\nexit;\n", script))
+			.unwrap();
+		interpreter
+			.run()
+			.unwrap_or_else(|e| panic!("the script failed: {}", e));
+	}
+
+	fn global(interpreter: &Interpreter, name: &str) -> Variable {
+		interpreter.variables[0].scopes[0]
+			.get(name)
+			.unwrap_or_else(|| panic!("no global variable `{}`", name))
+			.clone()
+	}
+
+	#[test]
+	fn jumping_out_of_a_loop_leaves_it() {
+		let mut interpreter = run_script(
+			"
+			let i = 0;
+			label again;
+			set i = i + 1;
+			if i < 3;
+				while true;
+					jump again;
+				endwhile;
+			endif;",
+		);
+		assert!(interpreter.loops.is_empty(), "{:?}", interpreter.loops);
+		run_more(
+			&mut interpreter,
+			"
+			let caught = false;
+			try;
+				break;
+			catch e;
+				set caught = message e;
+			endtry;",
+		);
+		assert_eq!(
+			global(&interpreter, "caught"),
+			variable::string("break outside of a loop")
+		);
+	}
+
+	#[test]
+	fn failed_conditions_run_no_branch() {
+		let interpreter = run_script(
+			"
+			let out = 0;
+			try;
+				if false;
+				elif (1 / 0) == 0;
+					set out = 1;
+				endif;
+			catch e;
+			endtry;
+			if true;
+				set out = out * 10 + 2;
+			elif true;
+				set out = out * 10 + 3;
+			endif;",
+		);
+		assert_eq!(global(&interpreter, "out"), Int(2));
+
+		let lenient = Options {
+			strict_files: false,
+			..Options::default()
+		};
+		let mut interpreter = Interpreter::new(Code::new(), lenient);
+		run_more(
+			&mut interpreter,
+			"
+			let out = 0;
+			if true;
+				let inner = 1;
+				if (1 / 0) == 0;
+					set out = 100;
+				elif true;
+					set out = 200;
+				else;
+					set out = 300;
+				endif;
+				set out = out + inner;
+			endif;",
+		);
+		assert_eq!(global(&interpreter, "out"), Int(1));
+	}
+
+	#[test]
+	fn statements_that_are_skipped_do_not_have_to_parse() {
+		let interpreter = run_script(
+			"
+			let caught = false;
+			try;
+				let x = );
+			catch e;
+				set caught = kind e;
+			endtry;
+			if false;
+				let y = );
+			endif;
+			fn f;
+				let z = );
+			end;
+			let after = 5;",
+		);
+		assert_eq!(global(&interpreter, "caught"), variable::string("parse"));
+		assert_eq!(global(&interpreter, "after"), Int(5));
+	}
+
+	fn ints(items: &[i64]) -> Variable {
+		List(IntT, Rc::new(items.iter().map(|&i| Int(i)).collect()))
+	}
+
+	#[test]
+	fn operands_are_read_before_the_calls_after_them() {
+		let interpreter = run_script(
+			"
+			let x = 1;
+			fn f -> int; global x; set x = 100; return 0; end;
+			let y = x + f();
+			let xs = [1 2];
+			fn g -> int; global xs; set xs = xs + 9; return 0; end;
+			let xs = xs + g();
+			let ys = [1 2];
+			fn h -> int; global ys; set ys = ys + 9; return 0; end;
+			set ys = ys + h();",
+		);
+		assert_eq!(global(&interpreter, "y"), Int(1));
+		assert_eq!(global(&interpreter, "x"), Int(100));
+		assert_eq!(global(&interpreter, "xs"), ints(&[1, 2, 0]));
+		assert_eq!(global(&interpreter, "ys"), ints(&[1, 2, 0]));
+	}
+
+	#[test]
+	fn errors_unwind_through_calls() {
+		let interpreter = run_script(
+			"
+			fn fail k int -> int; return k / 0; end;
+			fn twice k int -> int; return fail(k) * 2; end;
+			let caught = false;
+			try;
+				let never = twice(1);
+			catch e;
+				set caught = message e;
+			endtry;
+			fn recover -> int; try; return fail(1); catch e; return 7; endtry; end;
+			let recovered = recover() + 1;",
+		);
+		assert_eq!(
+			global(&interpreter, "caught"),
+			variable::string("division by zero")
+		);
+		assert_eq!(global(&interpreter, "recovered"), Int(8));
+		assert!(interpreter.call_stack.is_empty());
+	}
+
+	#[test]
+	fn assigning_to_captured_variables_changes_only_that_call() {
+		let interpreter = run_script(
+			"
+			fn make start int; let offset = 10; fn bump x int -> int; set start = start + x; return start + offset; end; return bump; end;
+			fn unchanged start int -> int; fn bump x int -> int; set start = start + x; return start; end; let ignored = bump(5); return start; end;
+			let bump = make(1);
+			let first = bump(5);
+			let second = bump(5);
+			let kept = unchanged(1);",
+		);
+		assert_eq!(global(&interpreter, "first"), Int(16));
+		assert_eq!(global(&interpreter, "second"), Int(16));
+		assert_eq!(global(&interpreter, "kept"), Int(1));
+	}
+
+	#[test]
+	fn variables_can_have_the_names_of_builtins() {
+		let interpreter = run_script(
+			"
+			let max = 3;
+			let step = 2;
+			let kind = max + step;
+			let xs = 0..10 step step;
+			let sign = sign (0 - kind);
+			fn log x int -> int; return x * 10; end;
+			let logged = log(max);
+			let n = xs len;
+			let ns = [(n - 1) abs -n] ++ (0..n step 2);",
+		);
+		assert_eq!(global(&interpreter, "kind"), Int(5));
+		assert_eq!(global(&interpreter, "xs"), ints(&[0, 2, 4, 6, 8]));
+		assert_eq!(global(&interpreter, "sign"), Int(-1));
+		assert_eq!(global(&interpreter, "logged"), Int(30));
+		assert_eq!(global(&interpreter, "n"), Int(5));
+		assert_eq!(global(&interpreter, "ns"), ints(&[4, 5, 0, 2, 4]));
+	}
+
+	#[test]
+	fn builtin_callbacks_run_once_per_item() {
+		let interpreter = run_script(
+			"
+			let calls = 0;
+			fn inc x int -> int; global calls; set calls = calls + 1; return x + 1; end;
+			fn add a int b int -> int; global calls; set calls = calls + 1; return a + b; end;
+			let xs = 0..2000;
+			let ys = [calls] ++ map(xs inc);
+			let total = fold(ys 0 add);",
+		);
+		let ys = (0..=2000).collect::<Vec<_>>();
+		assert_eq!(global(&interpreter, "ys"), ints(&ys));
+		assert_eq!(global(&interpreter, "total"), Int(ys.iter().sum()));
+		assert_eq!(global(&interpreter, "calls"), Int(2000 + 2001));
 	}
 }
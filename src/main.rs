@@ -1,37 +1,76 @@
 use std::collections::HashMap;
-use std::{env, fs, io, io::Write};
+use std::{env, fs, io, io::Write, panic, process, thread};
 
+pub mod ast;
 pub mod bools;
 pub mod chars;
 pub mod errors;
 pub mod file;
 pub mod floats;
-pub mod helper;
+pub mod lexer;
 pub mod list;
 pub mod logic;
+pub mod parser;
 pub mod variable;
+use ast::{BinaryOp, Builtin, Expr, ExprKind, Statement, UnaryOp};
 use errors::*;
 use file::Code;
 use variable::{
-	CallStack, Functions, Labels, Variable, Variable::*, VariableT, VariableT::*, Variables,
+	Call, CallStack, Closure, Context, Frame, Handlers, Labels, Variable, Variable::*, VariableT,
+	VariableT::*, Variables,
 };
 
-const KEYWORDS: [&str; 46] = [
-	"let", "if", "endif", "print", "clear", "label", "jump", "jump_rel", "type", "end", "fn",
-	"last", "len", "exit", "return", "n", "dig", "num", "c", "bool", "list", "char", "f64", "+",
-	"-", "*", "/", "%", "@", "++", "^", "!", "=", "==", "<", "<=", ">", ">=", "\"", "'", "(", ")",
-	"[", "]", "{", "}",
+const KEYWORDS: [&str; 60] = [
+	"let", "set", "global", "if", "elif", "else", "endif", "print", "clear", "label", "jump",
+	"jump_rel", "type", "end", "fn", "last", "len", "exit", "return", "inf", "nan", "puts", "true",
+	"false", "try", "catch", "endtry", "while", "endwhile", "for", "in", "endfor", "break",
+	"continue", "..", "..=", "->", "+", "-", "*", "/", "%", "@", "++", "^", "!", "=", "==", "<",
+	"<=", ">", ">=", "\"", "'", "(", ")", "[", "]", "{", "}",
 ];
 
 fn main() {
-	let mut code = Code::new();
-	for file in env::args().skip(1) {
-		code.import(&file)
-			.unwrap_or_else(|_| panic!("Couldn't read file: {}", &file));
+	let mut files = Vec::new();
+	let mut options = logic::Options::default();
+	for arg in env::args().skip(1) {
+		match arg.as_str() {
+			"--debug" => errors::DEBUG.store(true, std::sync::atomic::Ordering::Relaxed),
+			"--strict" => options.strict_repl = true,
+			"--lenient" => options.strict_files = false,
+			_ if arg.starts_with("--max-depth=") => {
+				let depth = &arg["--max-depth=".len()..];
+				options.max_call_depth = depth
+					.parse()
+					.unwrap_or_else(|_| panic!("Invalid maximum call depth: {}", depth));
+			}
+			_ => files.push(arg),
+		}
 	}
 
-	match logic::run(code) {
-		Ok(_) => {}
-		Err(e) => eprintln!("{}", e),
+	// Every function a script calls is run by a call of the interpreter, so the stack has to fit
+	// the deepest calls it allows
+	let stack_size = options
+		.max_call_depth
+		.saturating_add(1)
+		.saturating_mul(logic::STACK_PER_CALL);
+	let interpreter = thread::Builder::new()
+		.stack_size(stack_size)
+		.spawn(move || {
+			let mut code = Code::new();
+			for file in files {
+				code.import(&file)
+					.unwrap_or_else(|_| panic!("Couldn't read file: {}", &file));
+			}
+			logic::run(code, options).map_err(|e| {
+				// Errors in the script itself have already been reported by run
+				if e.downcast_ref::<CodeError>().is_none() {
+					eprintln!("{}", e);
+				}
+			})
+		})
+		.unwrap_or_else(|e| panic!("Couldn't start the interpreter: {}", e));
+	match interpreter.join() {
+		Ok(Ok(())) => {}
+		Ok(Err(())) => process::exit(1),
+		Err(e) => panic::resume_unwind(e),
 	}
 }
@@ -0,0 +1,791 @@
+use crate::*;
+use ast::{BinaryOp, Builtin, Expr, ExprKind, Params, Statement, UnaryOp};
+use lexer::Token;
+
+struct Parser {
+	tokens: Vec<(Token, Span)>,
+	index: usize,
+	len: usize,
+	// Index of the statement, which a function literal needs as its body follows it
+	statement: usize,
+	// Set once the statement has a function body following it, as it can only have one
+	opens_function: bool,
+}
+
+// `^` binds tighter than a leading `-`, so `-2 ^ 2` is -4
+const POW_PRECEDENCE: u8 = 7;
+
+// Operators of the same precedence are applied left to right, except for `^` which is applied right
+// to left
+fn binary_op(token: &Token) -> Option<(BinaryOp, u8)> {
+	let symbol = if let Token::Symbol(s) = token {
+		*s
+	} else {
+		return None;
+	};
+	let pair = match symbol {
+		"@" => (BinaryOp::Index, 10),
+		"&" => (BinaryOp::And, 9),
+		"|" => (BinaryOp::Or, 8),
+		"^" => (BinaryOp::Pow, POW_PRECEDENCE),
+		"*" => (BinaryOp::Mul, 6),
+		"/" => (BinaryOp::Div, 6),
+		"%" => (BinaryOp::Mod, 6),
+		"+" => (BinaryOp::Add, 5),
+		"++" => (BinaryOp::Join, 5),
+		"-" => (BinaryOp::Sub, 5),
+		"==" => (BinaryOp::Eq, 1),
+		"<" => (BinaryOp::Lt, 1),
+		"<=" => (BinaryOp::Le, 1),
+		">" => (BinaryOp::Gt, 1),
+		">=" => (BinaryOp::Ge, 1),
+		_ => return None,
+	};
+	Some(pair)
+}
+
+fn prefix_op(token: &Token) -> Option<UnaryOp> {
+	let word = if let Token::Word(w) = token {
+		w.as_str()
+	} else {
+		return None;
+	};
+	let op = match word {
+		"floor" => UnaryOp::Floor,
+		"ceil" => UnaryOp::Ceil,
+		"round" => UnaryOp::Round,
+		"sqrt" => UnaryOp::Sqrt,
+		"abs" => UnaryOp::Abs,
+		"trunc" => UnaryOp::Trunc,
+		"sign" => UnaryOp::Sign,
+		"sin" => UnaryOp::Sin,
+		"cos" => UnaryOp::Cos,
+		"tan" => UnaryOp::Tan,
+		"asin" => UnaryOp::Asin,
+		"acos" => UnaryOp::Acos,
+		"atan" => UnaryOp::Atan,
+		"ln" => UnaryOp::Ln,
+		"log10" => UnaryOp::Log10,
+		"log2" => UnaryOp::Log2,
+		"exp" => UnaryOp::Exp,
+		"n" => UnaryOp::CharToNum,
+		"dig" => UnaryOp::NumToDigit,
+		"num" => UnaryOp::NumToString,
+		"c" => UnaryOp::NumToChar,
+		"int" => UnaryOp::ToInt,
+		"float" => UnaryOp::ToFloat,
+		"kind" => UnaryOp::Kind,
+		"message" => UnaryOp::Message,
+		_ => return None,
+	};
+	Some(op)
+}
+
+fn builtin(token: &Token) -> Option<Builtin> {
+	let word = if let Token::Word(w) = token {
+		w.as_str()
+	} else {
+		return None;
+	};
+	let builtin = match word {
+		"map" => Builtin::Map,
+		"filter" => Builtin::Filter,
+		"fold" => Builtin::Fold,
+		"reduce" => Builtin::Reduce,
+		"sort" => Builtin::Sort,
+		"sort_by" => Builtin::SortBy,
+		"reverse" => Builtin::Reverse,
+		"find" => Builtin::Find,
+		"any" => Builtin::Any,
+		"all" => Builtin::All,
+		"zip" => Builtin::Zip,
+		"enumerate" => Builtin::Enumerate,
+		"min" => Builtin::Min,
+		"max" => Builtin::Max,
+		"clamp" => Builtin::Clamp,
+		"atan2" => Builtin::Atan2,
+		"hypot" => Builtin::Hypot,
+		"log" => Builtin::Log,
+		_ => return None,
+	};
+	Some(builtin)
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.index).map(|(token, _)| token)
+	}
+
+	// The span of the token at `index`, or the end of the line if there is none
+	fn span_of(&self, index: usize) -> Span {
+		self.tokens
+			.get(index)
+			.map(|(_, span)| *span)
+			.unwrap_or((self.len, self.len + 1))
+	}
+
+	fn span_from(&self, start: usize) -> Span {
+		let (from, _) = self.span_of(start);
+		let (_, to) = self.span_of(self.index.saturating_sub(1));
+		(from, to.max(from))
+	}
+
+	fn expr(&self, kind: ExprKind, start: usize) -> Expr {
+		Expr {
+			kind,
+			span: self.span_from(start),
+		}
+	}
+
+	fn is_symbol(&self, symbol: &str) -> bool {
+		self.is_symbol_at(self.index, symbol)
+	}
+
+	fn is_symbol_at(&self, index: usize, symbol: &str) -> bool {
+		matches!(self.tokens.get(index), Some((Token::Symbol(s), _)) if *s == symbol)
+	}
+
+	fn is_word(&self, word: &str) -> bool {
+		matches!(self.peek(), Some(Token::Word(w)) if w == word)
+	}
+
+	fn found(&self) -> String {
+		match self.peek() {
+			Some(token) => format!("`{}`", token),
+			None => "end of line".to_owned(),
+		}
+	}
+
+	fn expect_symbol(&mut self, symbol: &str) -> Result<(), CustomErr> {
+		if self.is_symbol(symbol) {
+			self.index += 1;
+			Ok(())
+		} else {
+			perr!("expected `{}`, found {}", symbol, self.found())
+		}
+	}
+
+	fn finish(&self) -> Result<(), CustomErr> {
+		if self.index >= self.tokens.len() {
+			Ok(())
+		} else {
+			perr!("expected end of statement, found {}", self.found())
+		}
+	}
+
+	fn name(&mut self) -> Result<String, CustomErr> {
+		match self.peek() {
+			Some(Token::Word(w)) if variable::is_ok(w) => {
+				let name = w.clone();
+				self.index += 1;
+				Ok(name)
+			}
+			Some(Token::Word(w)) => serr!("`{}` is a keyword and can't be used as a name", w),
+			_ => perr!("expected a name, found {}", self.found()),
+		}
+	}
+
+	fn starts_operand(&self) -> bool {
+		self.operand_at(self.index)
+	}
+
+	fn operand_at(&self, index: usize) -> bool {
+		match self.tokens.get(index).map(|(token, _)| token) {
+			Some(Token::Symbol(s)) => *s == "(" || *s == "[",
+			Some(_) => true,
+			None => false,
+		}
+	}
+
+	// `step` followed by the size of the step, rather than a variable called `step`
+	fn is_step(&self) -> bool {
+		self.is_step_at(self.index)
+	}
+
+	fn is_step_at(&self, index: usize) -> bool {
+		matches!(self.tokens.get(index), Some((Token::Word(w), _)) if w == "step")
+			&& (self.operand_at(index + 1) || self.is_symbol_at(index + 1, "-"))
+	}
+
+	// The prefix operator at `index` if its operand follows it, otherwise the name is a variable's.
+	// `abs -x` negates the operand, while `n - 1` subtracts from a variable called `n`
+	fn prefix_op_at(&self, index: usize) -> Option<UnaryOp> {
+		let (token, (_, end)) = self.tokens.get(index)?;
+		let op = prefix_op(token)?;
+		let has_operand = match self.tokens.get(index + 1) {
+			Some((Token::Word(w), _)) if w == "step" => !self.is_step_at(index + 1),
+			Some((Token::Word(w), _)) => {
+				variable::is_ok(w)
+					|| ["fn", "true", "false", "inf", "nan", "last"].contains(&w.as_str())
+			}
+			Some((Token::Symbol("-"), (minus_start, minus_end))) => {
+				let attached = matches!(
+					self.tokens.get(index + 2),
+					Some((_, (start, _))) if start == minus_end
+				);
+				end < minus_start && attached
+			}
+			_ => self.operand_at(index + 1),
+		};
+		Some(op).filter(|_| has_operand)
+	}
+
+	// A `-` between operands negates the next one, so `[1 -2]` has two items. Before a `)` it's the
+	// operator given to a built-in like `fold(xs 0 -)`
+	fn starts_negation(&self) -> bool {
+		self.is_symbol("-")
+			&& (self.operand_at(self.index + 1) || self.is_symbol_at(self.index + 1, "-"))
+	}
+
+	fn typ(&mut self) -> Result<VariableT, CustomErr> {
+		let typ = match self.peek() {
+			Some(Token::Word(w)) => match w.as_str() {
+				"f64" => NumberT,
+				"num" => NumberT,
+				"i64" => IntT,
+				"int" => IntT,
+				"bool" => BooleanT,
+				"char" => CharT,
+				"error" => ErrorT,
+				_ => return serr!("expected a type, found {}", self.found()),
+			},
+			Some(Token::Symbol("(")) => {
+				self.index += 1;
+				let typ = if self.is_word("list") {
+					self.index += 1;
+					ListT(Box::new(self.typ()?))
+				} else if self.is_word("fn") {
+					self.index += 1;
+					self.function_type()?
+				} else {
+					return serr!("expected `list` or `fn`, found {}", self.found());
+				};
+				if !self.is_symbol(")") {
+					return serr!("expected `)`, found {}", self.found());
+				}
+				typ
+			}
+			_ => return serr!("expected a type, found {}", self.found()),
+		};
+		self.index += 1;
+		Ok(typ)
+	}
+
+	// `Type Type -> Type` inside `(fn ...)`
+	fn function_type(&mut self) -> Result<VariableT, CustomErr> {
+		let mut params = Vec::new();
+		while self.peek().is_some() && !self.is_symbol("->") && !self.is_symbol(")") {
+			params.push(self.typ()?);
+		}
+		let returns = if self.is_symbol("->") {
+			self.index += 1;
+			Some(Box::new(self.typ()?))
+		} else {
+			None
+		};
+		Ok(FunctionT(params, returns))
+	}
+
+	fn expression(&mut self, min_precedence: u8) -> Result<Expr, CustomErr> {
+		let start = self.index;
+		if self.is_symbol("!") {
+			self.index += 1;
+			let operand = self.expression(0)?;
+			return Ok(self.expr(ExprKind::Unary(UnaryOp::Not, Box::new(operand)), start));
+		}
+		let mut lhs = if self.is_symbol("-") {
+			self.negation()?
+		} else {
+			self.operand()?
+		};
+		while let Some((op, precedence)) = self.peek().and_then(binary_op) {
+			if precedence < min_precedence {
+				break;
+			}
+			self.index += 1;
+			let rhs_start = self.index;
+			let rhs_precedence = if op == BinaryOp::Pow {
+				precedence
+			} else {
+				precedence + 1
+			};
+			let mut rhs = self.expression(rhs_precedence)?;
+			if op == BinaryOp::Index && self.is_range() {
+				rhs = self.range(rhs, rhs_start, precedence + 1, true)?;
+			}
+			let kind = if let (BinaryOp::Index, ExprKind::Range(..)) = (op, &rhs.kind) {
+				ExprKind::Slice(Box::new(lhs), Box::new(rhs))
+			} else if op == BinaryOp::Add && self.starts_operand() {
+				let item = self.expression(precedence + 1)?;
+				ExprKind::Insert(Box::new(lhs), Box::new(rhs), Box::new(item))
+			} else {
+				ExprKind::Binary(op, Box::new(lhs), Box::new(rhs))
+			};
+			lhs = self.expr(kind, start);
+		}
+		// Ranges bind looser than any operator, so `0..n + 1` ends at `n + 1`
+		if min_precedence == 0 && self.is_range() {
+			lhs = self.range(lhs, start, 1, false)?;
+		}
+		Ok(lhs)
+	}
+
+	// A leading `-` and what it negates, which only takes a `^` along with its operand, so `-2 ^ 2` is
+	// -4 but `-i @ 0` indexes with -i
+	fn negation(&mut self) -> Result<Expr, CustomErr> {
+		let start = self.index;
+		if let Some(min) = self.min_literal() {
+			return Ok(min);
+		}
+		self.index += 1;
+		let operand = if self.is_symbol("-") {
+			self.negation()?
+		} else {
+			self.operand()?
+		};
+		let operand = if self.is_symbol("^") {
+			self.index += 1;
+			let exponent = self.expression(POW_PRECEDENCE)?;
+			let kind = ExprKind::Binary(BinaryOp::Pow, Box::new(operand), Box::new(exponent));
+			self.expr(kind, start + 1)
+		} else {
+			operand
+		};
+		Ok(self.expr(ExprKind::Unary(UnaryOp::Neg, Box::new(operand)), start))
+	}
+
+	fn is_range(&self) -> bool {
+		self.is_symbol("..") || self.is_symbol("..=")
+	}
+
+	// The rest of a range after its start: `..end` or `..=end` and then `step size`
+	fn range(
+		&mut self,
+		from: Expr,
+		start: usize,
+		precedence: u8,
+		slice: bool,
+	) -> Result<Expr, CustomErr> {
+		let inclusive = self.is_symbol("..=");
+		self.index += 1;
+		// `list @ 2..` slices to the end of the list
+		let has_end = self.starts_operand() || self.starts_negation();
+		let end = if slice && (!has_end || self.is_step()) {
+			None
+		} else {
+			Some(Box::new(self.expression(precedence)?))
+		};
+		let step = if self.is_step() {
+			self.index += 1;
+			Some(Box::new(self.expression(precedence)?))
+		} else {
+			None
+		};
+		let kind = ExprKind::Range(Box::new(from), end, inclusive, step);
+		Ok(self.expr(kind, start))
+	}
+
+	// `-9223372036854775808`, whose digits alone are too large for an Int
+	fn min_literal(&mut self) -> Option<Expr> {
+		let start = self.index;
+		let min = self.is_symbol("-")
+			&& matches!(self.tokens.get(start + 1), Some((Token::Int(i64::MIN), _)));
+		if !min {
+			return None;
+		}
+		self.index += 2;
+		Some(self.expr(ExprKind::Literal(Int(i64::MIN)), start))
+	}
+
+	fn operand(&mut self) -> Result<Expr, CustomErr> {
+		let start = self.index;
+		if let Some(min) = self.min_literal() {
+			return Ok(min);
+		}
+		if self.is_symbol("-") {
+			self.index += 1;
+			let operand = self.operand()?;
+			return Ok(self.expr(ExprKind::Unary(UnaryOp::Neg, Box::new(operand)), start));
+		}
+		if let Some(op) = self.prefix_op_at(start) {
+			self.index += 1;
+			let operand = self.operand()?;
+			return Ok(self.expr(ExprKind::Unary(op, Box::new(operand)), start));
+		}
+		let mut expr = self.atom()?;
+		while self.is_word("len") {
+			self.index += 1;
+			expr = self.expr(ExprKind::Unary(UnaryOp::Len, Box::new(expr)), start);
+		}
+		Ok(expr)
+	}
+
+	fn operands(&mut self) -> Result<Vec<Expr>, CustomErr> {
+		let mut vec = Vec::new();
+		while self.starts_operand() || self.starts_negation() {
+			vec.push(self.operand()?);
+		}
+		Ok(vec)
+	}
+
+	fn atom(&mut self) -> Result<Expr, CustomErr> {
+		let start = self.index;
+		let kind = match self.peek() {
+			Some(Token::Number(n)) => ExprKind::Literal(Number(*n)),
+			Some(Token::Int(i64::MIN)) => {
+				let max = i64::MIN.unsigned_abs();
+				return perr!("{} is too large for an Int unless it's negated", max);
+			}
+			Some(Token::Int(n)) => ExprKind::Literal(Int(*n)),
+			Some(Token::Char(c)) => ExprKind::Literal(Char(*c)),
+			Some(Token::Str(s)) => ExprKind::Literal(variable::string(s)),
+			// Only a call of a built-in's name is the built-in, so it can be used as a variable's
+			Some(token) if builtin(token).is_some() && self.is_call(self.index) => {
+				return self.builtin_call();
+			}
+			Some(Token::Word(w)) if variable::is_ok(w) && self.is_call(self.index) => {
+				return self.function_call();
+			}
+			Some(Token::Word(w)) if w == "fn" => {
+				self.index += 1;
+				self.open_function(start)?;
+				let (args, returns) = self.signature()?;
+				return Ok(self.expr(ExprKind::Function(args, returns, self.statement), start));
+			}
+			Some(Token::Word(w)) => match w.as_str() {
+				"true" => ExprKind::Literal(Boolean(true)),
+				"false" => ExprKind::Literal(Boolean(false)),
+				"inf" | "nan" => ExprKind::Literal(Number(floats::evaluate_float(w)?)),
+				"last" => ExprKind::Name(w.clone()),
+				_ if variable::is_ok(w) => ExprKind::Name(w.clone()),
+				_ => return perr!("expected an expression, found keyword `{}`", w),
+			},
+			Some(Token::Symbol("(")) => {
+				self.index += 1;
+				let mut expr = self.expression(0)?;
+				self.expect_symbol(")")?;
+				expr.span = self.span_from(start);
+				return Ok(expr);
+			}
+			Some(Token::Symbol("[")) => {
+				self.index += 1;
+				let items = self.operands()?;
+				self.expect_symbol("]")?;
+				if items.is_empty() {
+					self.index = start;
+					return perr!("can't infer the type of an empty list");
+				}
+				return Ok(self.expr(ExprKind::List(items), start));
+			}
+			_ => return perr!("expected an expression, found {}", self.found()),
+		};
+		self.index += 1;
+		Ok(self.expr(kind, start))
+	}
+
+	fn open_function(&mut self, start: usize) -> Result<(), CustomErr> {
+		if self.opens_function {
+			self.index = start;
+			return perr!("a statement can only define one function, as its body follows it");
+		}
+		self.opens_function = true;
+		Ok(())
+	}
+
+	fn function(&mut self) -> Result<Statement, CustomErr> {
+		self.open_function(self.index)?;
+		let name = self.name()?;
+		let (args, returns) = self.signature()?;
+		Ok(Statement::Function(name, args, returns))
+	}
+
+	// The parameters and return type of a function, up to the end of the statement or a closing
+	// bracket
+	fn signature(&mut self) -> Result<(Params, Option<VariableT>), CustomErr> {
+		let mut args: Params = Vec::new();
+		while self.peek().is_some()
+			&& !self.is_symbol("->")
+			&& !self.is_symbol(")")
+			&& !self.is_symbol("]")
+		{
+			let start = self.index;
+			let arg = self.name()?;
+			let typ = self.typ()?;
+			let default = if self.is_symbol("=") {
+				self.index += 1;
+				Some(self.operand()?)
+			} else {
+				None
+			};
+			if default.is_none() && args.iter().any(|(_, _, d)| d.is_some()) {
+				self.index = start;
+				return perr!(
+					"`{}` needs a default value as it comes after one that has one",
+					arg
+				);
+			}
+			args.push((arg, typ, default));
+		}
+		let returns = if self.is_symbol("->") {
+			self.index += 1;
+			Some(self.typ()?)
+		} else {
+			None
+		};
+		Ok((args, returns))
+	}
+
+	// `name [@ index...] [type] = value` or just `name [@ index...]` after `let` and `set`
+	fn assignment(
+		&mut self,
+		statement: fn(String, Vec<Expr>, Option<VariableT>, Option<Expr>) -> Statement,
+	) -> Result<Statement, CustomErr> {
+		let name = self.name()?;
+		let mut indices = Vec::new();
+		while self.is_symbol("@") {
+			self.index += 1;
+			// Binds as tightly as the index of `@` does in an expression
+			indices.push(self.expression(11)?);
+		}
+		if self.peek().is_none() {
+			return Ok(statement(name, indices, None, None));
+		}
+		let typ = if self.is_symbol("=") {
+			None
+		} else {
+			Some(self.typ()?)
+		};
+		self.expect_symbol("=")?;
+		let value = self.expression(0)?;
+		Ok(statement(name, indices, typ, Some(value)))
+	}
+
+	fn globals(&mut self) -> Result<Statement, CustomErr> {
+		let mut names = vec![self.name()?];
+		while self.peek().is_some() {
+			names.push(self.name()?);
+		}
+		Ok(Statement::Global(names))
+	}
+
+	fn for_loop(&mut self) -> Result<Statement, CustomErr> {
+		let name = self.name()?;
+		if !self.is_word("in") {
+			return perr!("expected `in`, found {}", self.found());
+		}
+		self.index += 1;
+		let iterable = self.expression(0)?;
+		// Counting up by one doesn't need the whole list
+		match iterable.kind {
+			ExprKind::Range(start, Some(end), false, None) => {
+				Ok(Statement::ForRange(name, *start, *end))
+			}
+			_ => Ok(Statement::ForEach(name, iterable)),
+		}
+	}
+
+	// `(` right after the token at `index` with nothing in between, so that `print x (y)` still
+	// prints two values
+	fn is_call(&self, index: usize) -> bool {
+		match (self.tokens.get(index), self.tokens.get(index + 1)) {
+			(Some((_, (_, end))), Some((Token::Symbol("("), (start, _)))) => end == start,
+			_ => false,
+		}
+	}
+
+	fn function_call(&mut self) -> Result<Expr, CustomErr> {
+		let start = self.index;
+		let name = self.name()?;
+		let mut function = self.expr(ExprKind::Name(name), start);
+		// `f(x)(y)` calls the function that `f(x)` returns
+		while self.is_call(self.index - 1) {
+			self.expect_symbol("(")?;
+			let args = self.operands()?;
+			self.expect_symbol(")")?;
+			function = self.expr(ExprKind::Call(Box::new(function), args), start);
+		}
+		Ok(function)
+	}
+
+	// `map(xs f)`, or `fold(xs 0 +)` with an operator in place of the function
+	fn builtin_call(&mut self) -> Result<Expr, CustomErr> {
+		let start = self.index;
+		let builtin = self.peek().and_then(builtin).ok_or(perrE!())?;
+		self.index += 1;
+		self.expect_symbol("(")?;
+		let args = self.operands()?;
+		let operator = self.peek().and_then(binary_op).map(|(op, _)| op);
+		if operator.is_some() {
+			self.index += 1;
+		}
+		self.expect_symbol(")")?;
+		Ok(self.expr(ExprKind::Builtin(builtin, args, operator), start))
+	}
+
+	fn statement(&mut self) -> Result<Statement, CustomErr> {
+		let keyword = match self.peek() {
+			Some(Token::Word(w)) => w.clone(),
+			_ => return Ok(Statement::Expr(self.expression(0)?)),
+		};
+		self.index += 1;
+		let statement = match keyword.as_str() {
+			"exit" => Statement::Exit,
+			"let" => self.assignment(Statement::Let)?,
+			"set" => self.assignment(Statement::Set)?,
+			"global" => self.globals()?,
+			"if" => Statement::If(self.expression(0)?),
+			"elif" => Statement::Elif(self.expression(0)?),
+			"else" => Statement::Else,
+			"endif" => Statement::EndIf,
+			"print" => Statement::Print(self.operands()?),
+			"clear" => Statement::Clear,
+			"label" => Statement::Label(self.name()?),
+			"jump" => Statement::Jump(self.name()?),
+			"jump_rel" => Statement::JumpRel(self.expression(0)?),
+			"type" => Statement::Type(self.expression(0)?),
+			"end" => Statement::End,
+			"return" if self.peek().is_some() => Statement::Return(Some(self.expression(0)?)),
+			"return" => Statement::Return(None),
+			"fn" => self.function()?,
+			"puts" => Statement::Puts(self.expression(0)?),
+			"try" => Statement::Try,
+			"catch" => Statement::Catch(self.name()?),
+			"endtry" => Statement::EndTry,
+			"while" => Statement::While(self.expression(0)?),
+			"endwhile" => Statement::EndWhile,
+			"for" => self.for_loop()?,
+			"endfor" => Statement::EndFor,
+			"break" => Statement::Break,
+			"continue" => Statement::Continue,
+			_ => {
+				self.index -= 1;
+				Statement::Expr(self.expression(0)?)
+			}
+		};
+		Ok(statement)
+	}
+}
+
+pub fn parse_statement(line: &str, index: usize) -> Result<Statement, CustomErr> {
+	let mut parser = Parser {
+		tokens: lexer::tokenize(line)?,
+		index: 0,
+		len: line.len(),
+		statement: index,
+		opens_function: false,
+	};
+	parser
+		.statement()
+		.and_then(|statement| parser.finish().map(|_| statement))
+		.map_err(|e| errors::at(e, parser.span_of(parser.index)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::collections::HashMap;
+	use std::rc::Rc;
+
+	// Expressions that don't call any functions
+	struct NoCalls(Variables);
+
+	impl Context for NoCalls {
+		fn variables(&self) -> &Variables {
+			&self.0
+		}
+
+		fn call(&mut self, _: &Rc<Closure>, _: Vec<Variable>) -> Result<Variable, CustomErr> {
+			panic!("called a function")
+		}
+	}
+
+	fn evaluate(line: &str) -> Variable {
+		let expr = match parse_statement(line, 0) {
+			Ok(Statement::Expr(expr)) => expr,
+			other => panic!("`{}` isn't an expression: {:?}", line, other),
+		};
+		let mut ctx = NoCalls(vec![Frame::new(Rc::default(), HashMap::new())]);
+		variable::evaluate_expression(&expr, &mut ctx)
+			.unwrap_or_else(|e| panic!("`{}` failed: {}", line, e))
+	}
+
+	#[test]
+	fn additive_operators_are_left_associative() {
+		assert_eq!(evaluate("10 - 2 + 3"), Int(11));
+		assert_eq!(evaluate("10 + 2 - 3"), Int(9));
+		assert_eq!(evaluate("1 - 2 - 3"), Int(-4));
+		assert_eq!(evaluate("[1 2] ++ [3] + 4"), evaluate("[1 2 3 4]"));
+	}
+
+	#[test]
+	fn multiplicative_operators_are_left_associative() {
+		assert_eq!(evaluate("8 / 2 * 2"), Int(8));
+		assert_eq!(evaluate("100 / 10 / 5"), Int(2));
+		assert_eq!(evaluate("10 % 4 * 3"), Int(6));
+		assert_eq!(evaluate("7 * 3 % 4"), Int(1));
+		assert_eq!(evaluate("1.0 / 2.0 * 4.0"), Number(2.));
+	}
+
+	#[test]
+	fn power_is_right_associative() {
+		assert_eq!(evaluate("2 ^ 3 ^ 2"), Int(512));
+		assert_eq!(evaluate("(2 ^ 3) ^ 2"), Int(64));
+		assert_eq!(evaluate("2.0 ^ -1.0 ^ 2.0"), Number(0.5));
+	}
+
+	#[test]
+	fn power_binds_tighter_than_negation() {
+		assert_eq!(evaluate("-2 ^ 2"), Int(-4));
+		assert_eq!(evaluate("(-2) ^ 2"), Int(4));
+		assert_eq!(evaluate("2 * -3 ^ 2"), Int(-18));
+		assert_eq!(evaluate("-2 * 3"), Int(-6));
+		assert_eq!(evaluate("- -2 ^ 2"), Int(4));
+	}
+
+	#[test]
+	fn negation_binds_tighter_than_other_operators() {
+		assert_eq!(evaluate("[1 2 3] @ -1"), Int(3));
+		assert_eq!(evaluate("[[1 2] [3 4]] @ -1 @ 0"), Int(3));
+		assert_eq!(evaluate("-2 ^ 2 * 3"), Int(-12));
+		match parse_statement("-a & b", 0) {
+			Ok(Statement::Expr(Expr {
+				kind: ExprKind::Binary(BinaryOp::And, lhs, _),
+				..
+			})) => assert!(matches!(lhs.kind, ExprKind::Unary(UnaryOp::Neg, _))),
+			other => panic!("`-a & b` isn't `(-a) & b`: {:?}", other),
+		}
+		match parse_statement("set xs @ -1 @ 0 = 5", 0) {
+			Ok(Statement::Set(_, indices, _, _)) => assert_eq!(indices.len(), 2),
+			other => panic!("`set xs @ -1 @ 0` isn't two indices: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn precedence_levels() {
+		assert_eq!(evaluate("2 + 3 * 4 ^ 2"), Int(50));
+		assert_eq!(evaluate("7 - 3 % 2"), Int(6));
+		assert_eq!(evaluate("1 + 2 * 3 - 4 / 2"), Int(5));
+		assert_eq!(evaluate("10 - 1 == 3 * 3"), Boolean(true));
+		assert_eq!(evaluate("[5 6 7] @ 1 * 2"), Int(12));
+	}
+
+	#[test]
+	fn slices_may_end_at_a_negative_index() {
+		assert_eq!(evaluate("[1 2 3 4 5] @ 1..-1"), evaluate("[2 3 4]"));
+		assert_eq!(evaluate("[1 2 3 4 5] @ -3..=-2"), evaluate("[3 4]"));
+		assert_eq!(evaluate("[1 2 3 4 5] @ 1..-(1 + 1)"), evaluate("[2 3]"));
+		assert_eq!(evaluate("[1 2 3 4 5] @ 1.. step 2"), evaluate("[2 4]"));
+	}
+
+	#[test]
+	fn smallest_int_literal() {
+		assert_eq!(evaluate("-9223372036854775808"), Int(i64::MIN));
+		assert_eq!(evaluate("-9223372036854775808 + 1"), Int(i64::MIN + 1));
+		assert_eq!(evaluate("[1 -9223372036854775808] @ 1"), Int(i64::MIN));
+		assert_eq!(evaluate("-0x8000000000000000"), Int(i64::MIN));
+		assert!(parse_statement("9223372036854775808", 0).is_err());
+		assert!(parse_statement("1 - 9223372036854775808", 0).is_err());
+		assert!(parse_statement("-9223372036854775809", 0).is_err());
+	}
+}
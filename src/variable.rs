@@ -1,26 +1,79 @@
 use crate::*;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::rc::Rc;
 
-pub type Variables = HashMap<String, Variable>;
-pub type Labels = HashMap<String, usize>;
-pub type Functions = HashMap<String, (Vec<(String, VariableT)>, usize)>;
-pub type CallStack = Vec<(HashMap<String, Variable>, usize)>;
+pub type Scope = HashMap<String, Variable>;
+// A frame for the top level, whose first scope holds the globals, and one for each function call
+pub type Variables = Vec<Frame>;
+// (index of the label, scope depth it was defined at)
+pub type Labels = HashMap<String, (usize, usize)>;
+// (name, type, default value)
+pub type Param = (String, VariableT, Option<Variable>);
+pub type CallStack = Vec<Call>;
+// (index of the try statement, call stack, loop and scope depth when it was entered)
+pub type Handlers = Vec<(usize, usize, usize, usize)>;
+
+/// The variables of one function call
+#[derive(Debug)]
+pub struct Frame {
+	/// What the function captured, shared by all of its calls. Assigning to one of them copies it
+	/// into the first scope
+	pub captured: Rc<Scope>,
+	/// Innermost last. The first one holds the arguments and `last`
+	pub scopes: Vec<Scope>,
+}
+
+impl Frame {
+	pub fn new(captured: Rc<Scope>, scope: Scope) -> Self {
+		Frame {
+			captured,
+			scopes: vec![scope],
+		}
+	}
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Closure {
+	pub name: String,
+	pub params: Vec<Param>,
+	pub returns: Option<VariableT>,
+	/// Index of the statement the body follows
+	pub body: usize,
+	/// The variables that were in scope where it was defined, other than the globals
+	pub captured: Rc<Scope>,
+}
+
+/// A function call that hasn't returned yet
+#[derive(Debug)]
+pub struct Call {
+	/// Index of the calling statement
+	pub call_site: usize,
+	pub name: String,
+	pub returns: Option<VariableT>,
+	/// The globals that it may assign to
+	pub globals: HashSet<String>,
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Variable {
 	Boolean(bool),
 	Number(f64),
+	Int(i64),
 	Char(char),
-	List(VariableT, Vec<Variable>),
+	// The items are shared between copies of the list until one of them is changed
+	List(VariableT, Rc<Vec<Variable>>),
+	Error(ErrorKind, String),
+	Function(Rc<Closure>),
 }
 
 impl fmt::Display for Variable {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
 			Boolean(b) => write!(f, "{}", b),
-			Number(n) => write!(f, "{}", n),
+			Number(n) => write!(f, "{}", floats::display(*n)),
+			Int(n) => write!(f, "{}", n),
 			Char(c) => write!(f, "{}", c),
 			List(t, l) => {
 				write!(f, "({}, {})[ ", t, l.len())?;
@@ -29,6 +82,14 @@ impl fmt::Display for Variable {
 				}
 				write!(f, "]")
 			}
+			Error(kind, message) => write!(f, "{} error: {}", kind, message),
+			Function(function) => {
+				write!(
+					f,
+					"{}",
+					signature(&function.name, &function.params, &function.returns)
+				)
+			}
 		}
 	}
 }
@@ -37,48 +98,41 @@ impl fmt::Display for Variable {
 pub enum VariableT {
 	BooleanT,
 	NumberT,
+	IntT,
 	CharT,
 	ListT(Box<VariableT>),
-}
-
-impl std::str::FromStr for VariableT {
-	type Err = CustomErr;
-	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let res = if helper::has_parentheses(s) {
-			let split = helper::split(helper::remove_parentheses(s))?;
-			match split.as_slice() {
-				["list", typ] => ListT(Box::new(typ.parse()?)),
-				[_, _] => return terr!(),
-				_ => return serr!(),
-			}
-		} else {
-			match s {
-				"f64" => NumberT,
-				"num" => NumberT,
-				"bool" => BooleanT,
-				"char" => CharT,
-				_ => return serr!(),
-			}
-		};
-		Ok(res)
-	}
+	ErrorT,
+	// Parameter types and return type
+	FunctionT(Vec<VariableT>, Option<Box<VariableT>>),
 }
 
 impl fmt::Display for VariableT {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		if let ListT(t) = self {
-			write!(f, "List of {}", t)
-		} else {
-			write!(
+		match self {
+			ListT(t) => write!(f, "List of {}", t),
+			FunctionT(params, returns) => {
+				let params = params
+					.iter()
+					.map(VariableT::to_string)
+					.collect::<Vec<_>>()
+					.join(", ");
+				match returns {
+					Some(returns) => write!(f, "Function({}) -> {}", params, returns),
+					None => write!(f, "Function({})", params),
+				}
+			}
+			_ => write!(
 				f,
 				"{}",
 				match self {
 					NumberT => "Number",
+					IntT => "Int",
 					CharT => "Char",
 					BooleanT => "Boolean",
-					ListT(_) => unreachable!(),
+					ErrorT => "Error",
+					ListT(_) | FunctionT(..) => unreachable!(),
 				}
-			)
+			),
 		}
 	}
 }
@@ -92,12 +146,52 @@ impl From<Variable> for VariableT {
 pub fn to_type(var: &Variable) -> VariableT {
 	match var {
 		Number(_) => NumberT,
+		Int(_) => IntT,
 		Char(_) => CharT,
 		Boolean(_) => BooleanT,
 		List(t, _) => ListT(Box::new(t.clone())),
+		Error(..) => ErrorT,
+		Function(function) => FunctionT(
+			function
+				.params
+				.iter()
+				.map(|(_, typ, _)| typ.clone())
+				.collect(),
+			function.returns.clone().map(Box::new),
+		),
+	}
+}
+
+/// `name(arg Type, arg Type = default) -> Type`
+pub fn signature(name: &str, params: &[Param], returns: &Option<VariableT>) -> String {
+	let params = params
+		.iter()
+		.map(|(arg, typ, default)| match default {
+			Some(default) => format!("{} {} = {}", arg, typ, default),
+			None => format!("{} {}", arg, typ),
+		})
+		.collect::<Vec<_>>()
+		.join(", ");
+	match returns {
+		Some(returns) => format!("{}({}) -> {}", name, params, returns),
+		None => format!("{}({})", name, params),
 	}
 }
 
+pub fn string(s: &str) -> Variable {
+	List(CharT, Rc::new(s.chars().map(Char).collect()))
+}
+
+/// The value bound by `catch`, if the error is one a script can catch
+pub fn from_error(error: &CustomErr) -> Option<Variable> {
+	let error = error.downcast_ref::<CodeError>()?;
+	let message = error
+		.message
+		.clone()
+		.unwrap_or_else(|| format!("{} error", error.kind));
+	Some(Error(error.kind, message))
+}
+
 pub fn assert_type_of(var: &Variable, typ: &VariableT) -> Result<(), CustomErr> {
 	assert_type(&to_type(var), typ)
 }
@@ -110,7 +204,7 @@ pub fn assert_type(t1: &VariableT, t2: &VariableT) -> Result<(), CustomErr> {
 	if *t1 == *t2 {
 		Ok(())
 	} else {
-		terr!()
+		terr!("expected {}, found {}", t2, t1)
 	}
 }
 
@@ -118,7 +212,7 @@ pub fn assert_list_type(t: &VariableT) -> Result<(), CustomErr> {
 	if let ListT(_) = t {
 		Ok(())
 	} else {
-		terr!()
+		terr!("expected List, found {}", t)
 	}
 }
 
@@ -126,7 +220,15 @@ pub fn un_number(var: &Variable) -> Result<f64, CustomErr> {
 	if let Number(n) = var {
 		Ok(*n)
 	} else {
-		terr!()
+		terr!("expected Number, found {}", to_type(var))
+	}
+}
+
+pub fn un_int(var: &Variable) -> Result<i64, CustomErr> {
+	if let Int(n) = var {
+		Ok(*n)
+	} else {
+		terr!("expected Int, found {}", to_type(var))
 	}
 }
 
@@ -134,7 +236,7 @@ pub fn un_bool(var: &Variable) -> Result<bool, CustomErr> {
 	if let Boolean(n) = var {
 		Ok(*n)
 	} else {
-		terr!()
+		terr!("expected Boolean, found {}", to_type(var))
 	}
 }
 
@@ -142,48 +244,293 @@ pub fn un_char(var: &Variable) -> Result<char, CustomErr> {
 	if let Char(n) = var {
 		Ok(*n)
 	} else {
-		terr!()
+		terr!("expected Char, found {}", to_type(var))
+	}
+}
+
+pub fn un_error(var: &Variable) -> Result<(ErrorKind, &str), CustomErr> {
+	if let Error(kind, message) = var {
+		Ok((*kind, message))
+	} else {
+		terr!("expected Error, found {}", to_type(var))
+	}
+}
+
+pub fn un_function(var: &Variable) -> Result<Rc<Closure>, CustomErr> {
+	if let Function(function) = var {
+		Ok(function.clone())
+	} else {
+		terr!("expected Function, found {}", to_type(var))
 	}
 }
 
-pub fn un_list(var: Variable) -> Result<(VariableT, Vec<Variable>), CustomErr> {
+pub fn un_list(var: Variable) -> Result<(VariableT, Rc<Vec<Variable>>), CustomErr> {
 	if let List(t, v) = var {
 		Ok((t, v))
 	} else {
-		terr!()
+		terr!("expected List, found {}", to_type(&var))
 	}
 }
 
-pub fn evaluate_statement(words: &[&str], variables: &Variables) -> Result<Variable, CustomErr> {
-	match words {
-		[] => perr!(),
-		[s] if helper::has_parentheses(s) => evaluate_statement(
-			&helper::split(helper::remove_parentheses(words[0]))?,
-			variables,
-		),
-		[s] if variables.contains_key(*s) => Ok(variables.get(*s).expect("Unreachable?").clone()),
-		_ => Err(Box::new(perrE!()) as Box<dyn std::error::Error>)
-			.or_else(|_| floats::evaluate_floats(words, variables))
-			.or_else(|_| list::list_op(words, variables))
-			.or_else(|_| bools::evaluate_bools(words, variables))
-			.or_else(|_| chars::char_op(words, variables)),
+/// Looks `name` up in the innermost scope of the current call that has it, and then among the
+/// globals
+pub fn get_variable<'a>(name: &str, variables: &'a Variables) -> Result<&'a Variable, CustomErr> {
+	let globals = variables
+		.first()
+		.filter(|_| variables.len() > 1)
+		.and_then(|frame| frame.scopes.first());
+	let frame = variables.last().into_iter();
+	frame
+		.clone()
+		.flat_map(|frame| frame.scopes.iter().rev())
+		.chain(frame.map(|frame| &*frame.captured))
+		.chain(globals)
+		.find_map(|scope| scope.get(name))
+		.ok_or_else(|| nerrE!("unknown variable `{}`", name).into())
+}
+
+/// Binds `name` in the innermost scope, shadowing any outer variable of the same name
+pub fn declare(name: &str, value: Variable, variables: &mut Variables) -> Result<(), CustomErr> {
+	variables
+		.last_mut()
+		.and_then(|frame| frame.scopes.last_mut())
+		.ok_or(serrE!())?
+		.insert(name.to_owned(), value);
+	Ok(())
+}
+
+/// Updates `last` for the current call
+pub fn set_last(value: Variable, variables: &mut Variables) -> Result<(), CustomErr> {
+	variables
+		.last_mut()
+		.and_then(|frame| frame.scopes.first_mut())
+		.ok_or(serrE!())?
+		.insert("last".to_owned(), value);
+	Ok(())
+}
+
+/// Copies of the variables a function defined here can see, other than the globals which it reads
+/// when it's called
+pub fn captured(variables: &Variables) -> Rc<Scope> {
+	let globals = if variables.len() == 1 { 1 } else { 0 };
+	let frame = match variables.last() {
+		Some(frame) => frame,
+		None => return Rc::default(),
+	};
+	let scopes = &frame.scopes[globals.min(frame.scopes.len())..];
+	// Nothing new to capture, so the function can share what the current one captured
+	if scopes
+		.iter()
+		.flat_map(|scope| scope.keys())
+		.all(|name| name == "last")
+	{
+		return frame.captured.clone();
+	}
+	let mut captured = (*frame.captured).clone();
+	for (name, value) in scopes.iter().flat_map(|scope| scope.iter()) {
+		if name != "last" {
+			captured.insert(name.clone(), value.clone());
+		}
 	}
+	Rc::new(captured)
 }
 
-pub fn is_ok(name: &str) -> bool {
-	!KEYWORDS.contains(&name)
-		&& !name.is_empty()
-		&& name.as_bytes().get(0).map(|d| d.is_ascii_digit()) != Some(true)
-		&& !helper::is_list(name)
-		&& !helper::is_string(name)
-		&& !helper::has_parentheses(name)
+/// Evaluates the default values of a function's parameters, which happens once when the function
+/// is defined
+pub fn parameters(args: &ast::Params, ctx: &mut dyn Context) -> Result<Vec<Param>, CustomErr> {
+	let mut params = Vec::with_capacity(args.len());
+	for (arg, typ, default) in args {
+		let default = match default {
+			Some(expr) => {
+				let value = evaluate_expression(expr, ctx)?;
+				let found = to_type(&value);
+				if found != *typ {
+					return terr!(
+						"default value of `{}`: expected {}, found {}",
+						arg,
+						typ,
+						found
+					);
+				}
+				Some(value)
+			}
+			None => None,
+		};
+		params.push((arg.clone(), typ.clone(), default));
+	}
+	Ok(params)
 }
 
-pub fn owned_name(name: Option<&&str>) -> Result<String, CustomErr> {
-	let name = name.ok_or(perrE!())?;
-	if is_ok(name) {
-		Ok(name.to_string())
-	} else {
-		serr!()
+/// How many scopes deep the current call is
+pub fn scope_depth(variables: &Variables) -> usize {
+	variables
+		.last()
+		.map(|frame| frame.scopes.len())
+		.unwrap_or(0)
+}
+
+/// Looks `name` up in the innermost of `scopes` that has it
+pub fn get_variable_mut<'a>(
+	name: &str,
+	scopes: &'a mut [Scope],
+) -> Result<&'a mut Variable, CustomErr> {
+	scopes
+		.iter_mut()
+		.rev()
+		.find_map(|scope| scope.get_mut(name))
+		.ok_or_else(|| nerrE!("unknown variable `{}`", name).into())
+}
+
+/// What expressions are evaluated in: the variables in scope, and a way to run the script functions
+/// they call
+pub trait Context {
+	fn variables(&self) -> &Variables;
+	/// Runs `function` with `args` until it returns, which happens before the expression goes on
+	fn call(&mut self, function: &Rc<Closure>, args: Vec<Variable>) -> Result<Variable, CustomErr>;
+}
+
+pub fn evaluate_expression(expr: &Expr, ctx: &mut dyn Context) -> Result<Variable, CustomErr> {
+	evaluate_kind(&expr.kind, ctx).map_err(|e| errors::at(e, expr.span))
+}
+
+fn evaluate_kind(kind: &ExprKind, ctx: &mut dyn Context) -> Result<Variable, CustomErr> {
+	let val = match kind {
+		ExprKind::Literal(val) => val.clone(),
+		// A variable hides the constant of the same name
+		ExprKind::Name(name) => match get_variable(name, ctx.variables()) {
+			Ok(value) => value.clone(),
+			Err(e) => floats::constant(name).ok_or(e)?,
+		},
+		ExprKind::List(items) => list::evaluate_list(items, ctx)?,
+		ExprKind::Unary(op, operand) => {
+			let operand = evaluate_expression(operand, ctx)?;
+			match op {
+				UnaryOp::Not => bools::not(operand)?,
+				UnaryOp::Neg => floats::negate(operand)?,
+				UnaryOp::Len => Int(list::list_len(&operand)? as i64),
+				UnaryOp::Floor
+				| UnaryOp::Ceil
+				| UnaryOp::Round
+				| UnaryOp::Sqrt
+				| UnaryOp::Abs
+				| UnaryOp::Trunc
+				| UnaryOp::Sign
+				| UnaryOp::Sin
+				| UnaryOp::Cos
+				| UnaryOp::Tan
+				| UnaryOp::Asin
+				| UnaryOp::Acos
+				| UnaryOp::Atan
+				| UnaryOp::Ln
+				| UnaryOp::Log10
+				| UnaryOp::Log2
+				| UnaryOp::Exp => floats::round_op(*op, operand)?,
+				UnaryOp::ToInt | UnaryOp::ToFloat => floats::convert_op(*op, operand)?,
+				UnaryOp::Kind => string(&un_error(&operand)?.0.to_string()),
+				UnaryOp::Message => string(un_error(&operand)?.1),
+				_ => chars::convert_op(*op, operand)?,
+			}
+		}
+		ExprKind::Binary(op, lhs, rhs) => binary_op(
+			*op,
+			evaluate_expression(lhs, ctx)?,
+			evaluate_expression(rhs, ctx)?,
+		)?,
+		ExprKind::Insert(list, index, item) => list::add_to_list(
+			evaluate_expression(list, ctx)?,
+			evaluate_expression(index, ctx)?,
+			evaluate_expression(item, ctx)?,
+		)?,
+		ExprKind::Call(function, args) => {
+			let function = match &function.kind {
+				ExprKind::Name(name) => get_variable(name, ctx.variables())
+					.map_err(|_| nerrE!("unknown function `{}`", name))?
+					.clone(),
+				_ => evaluate_expression(function, ctx)?,
+			};
+			let function = un_function(&function)?;
+			let args = args
+				.iter()
+				.map(|arg| evaluate_expression(arg, ctx))
+				.collect::<Result<Vec<_>, _>>()?;
+			ctx.call(&function, args)?
+		}
+		// A variable hides the built-in of the same name, and is called instead
+		ExprKind::Builtin(builtin, args, operator) => {
+			let function = get_variable(&builtin.to_string(), ctx.variables())
+				.map(un_function)
+				.ok()
+				.transpose()?;
+			let args = args
+				.iter()
+				.map(|arg| evaluate_expression(arg, ctx))
+				.collect::<Result<Vec<_>, _>>()?;
+			match (function, operator) {
+				(Some(_), Some(op)) => {
+					return terr!("`{}` is a variable and can't be given `{}`", builtin, op)
+				}
+				(Some(function), None) => ctx.call(&function, args)?,
+				(None, _) => list::builtin(*builtin, args, *operator, ctx)?,
+			}
+		}
+		ExprKind::Range(start, end, inclusive, step) => {
+			let start = evaluate_expression(start, ctx)?;
+			let end = end
+				.as_ref()
+				.ok_or(perrE!("expected the end of the range"))?;
+			let end = evaluate_expression(end, ctx)?;
+			let step = step
+				.as_ref()
+				.map(|step| evaluate_expression(step, ctx))
+				.transpose()?;
+			list::range(start, end, *inclusive, step)?
+		}
+		ExprKind::Slice(list, range) => {
+			let list = evaluate_expression(list, ctx)?;
+			let (start, end, inclusive, step) = match &range.kind {
+				ExprKind::Range(start, end, inclusive, step) => (start, end, *inclusive, step),
+				_ => return serr!(),
+			};
+			let start = evaluate_expression(start, ctx)?;
+			let end = end
+				.as_ref()
+				.map(|end| evaluate_expression(end, ctx))
+				.transpose()?;
+			let step = step
+				.as_ref()
+				.map(|step| evaluate_expression(step, ctx))
+				.transpose()?;
+			list::slice(list, start, end, inclusive, step)?
+		}
+		ExprKind::Function(args, returns, index) => {
+			let params = parameters(args, ctx)?;
+			// Anonymous functions go by `fn` in signatures and stack traces
+			let function = Closure {
+				name: "fn".to_owned(),
+				params,
+				returns: returns.clone(),
+				body: *index,
+				captured: captured(ctx.variables()),
+			};
+			Function(Rc::new(function))
+		}
+	};
+	Ok(val)
+}
+
+pub fn binary_op(op: BinaryOp, lhs: Variable, rhs: Variable) -> Result<Variable, CustomErr> {
+	match lhs {
+		Boolean(_) => bools::bool_op(op, lhs, rhs),
+		Number(_) | Int(_) => floats::number_op(op, lhs, rhs),
+		Char(_) => chars::char_op(op, lhs, rhs),
+		List(..) => list::list_op(op, lhs, rhs),
+		Error(..) => terr!("`{}` is not defined for Error", op),
+		Function(..) => terr!("`{}` is not defined for {}", op, to_type(&lhs)),
 	}
 }
+
+pub fn is_ok(name: &str) -> bool {
+	!KEYWORDS.contains(&name)
+		&& !name.is_empty()
+		&& name.as_bytes().first().map(|d| d.is_ascii_digit()) != Some(true)
+}